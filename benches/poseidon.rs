@@ -0,0 +1,26 @@
+use ark_bn254::Fr;
+use criterion::{Criterion, criterion_group, criterion_main};
+use cswire::{
+    CS, Mode, V,
+    ark_poseidon::{PoseidonSponge, circom_bn254_poseidon_canonical_config},
+};
+
+fn absorb(mode: Mode, n: u64) -> Fr {
+    let cs = CS::<Fr>::new_ref(mode);
+    let config = circom_bn254_poseidon_canonical_config::<Fr>();
+    let mut sponge = PoseidonSponge::new(cs.clone(), &config);
+    let elems: Vec<V<Fr>> = (0..n).map(|i| cs.alloc(i).into()).collect();
+    sponge.absorb(&elems);
+    sponge.squeeze_native_field_elements(1)[0].raw()
+}
+
+fn bench_poseidon(c: &mut Criterion) {
+    let mut group = c.benchmark_group("poseidon_absorb_1k");
+    group.sample_size(10);
+    group.bench_function("run", |b| b.iter(|| absorb(Mode::Run, 1000)));
+    group.bench_function("compile", |b| b.iter(|| absorb(Mode::Compile, 1000)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_poseidon);
+criterion_main!(benches);
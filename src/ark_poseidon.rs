@@ -9,7 +9,7 @@ use ark_ff::PrimeField;
 use crate::{
     CSRef,
     utils::pow,
-    variables::{ConstraintSystemRef, V},
+    variables::{Const, ConstraintSystemRef, V},
 };
 
 /// The mode structure for duplex sponges
@@ -99,8 +99,8 @@ pub struct PoseidonSponge<F: PrimeField> {
 
     /// ConstraintSystem
     cs: ConstraintSystemRef<F>,
-    pub ark: Vec<Vec<V<F>>>,
-    pub mds: Vec<Vec<V<F>>>,
+    pub ark: Vec<Vec<Const<F>>>,
+    pub mds: Vec<Vec<Const<F>>>,
 }
 
 impl<F: PrimeField> PoseidonSponge<F> {
@@ -119,45 +119,51 @@ impl<F: PrimeField> PoseidonSponge<F> {
 
     fn apply_ark(&self, state: &mut [V<F>], round_number: usize) {
         for (i, state_elem) in state.iter_mut().enumerate() {
-            *state_elem += self.ark[round_number][i].clone();
+            *state_elem += self.ark[round_number][i];
         }
     }
 
+    // 定数倍は線形なので、MDSは制約を使わない
     fn apply_mds(&self, state: &mut [V<F>]) {
         let mut new_state = Vec::new();
         for i in 0..state.len() {
-            let mut cur = self.cs.one() * 0u32;
+            let mut cur = V::zero();
             for (j, state_elem) in state.iter().enumerate() {
-                let term = self.cs.wire(state_elem * &self.mds[i][j]);
-                cur += term;
+                cur += state_elem * self.mds[i][j];
             }
             new_state.push(cur);
         }
         state.clone_from_slice(&new_state[..state.len()])
     }
 
+    // 各ラウンドは poseidon/round_{i}/{sbox,mds} のnamespaceに記録される。
+    // 名前はMode::Compileでだけ文字列になる
+    fn apply_round(&self, state: &mut [V<F>], round_number: usize, is_full_round: bool) {
+        self.cs.namespace(format_args!("round_{round_number}"), |cs| {
+            self.apply_ark(state, round_number);
+            cs.namespace("sbox", |_| self.apply_s_box(state, is_full_round));
+            cs.namespace("mds", |_| self.apply_mds(state));
+        })
+    }
+
     fn permute(&mut self) {
         let full_rounds_over_2 = self.parameters.full_rounds / 2;
         let mut state = self.state.clone();
-        for i in 0..full_rounds_over_2 {
-            self.apply_ark(&mut state, i);
-            self.apply_s_box(&mut state, true);
-            self.apply_mds(&mut state);
-        }
+        self.cs.namespace("poseidon", |_| {
+            for i in 0..full_rounds_over_2 {
+                self.apply_round(&mut state, i, true);
+            }
 
-        for i in full_rounds_over_2..(full_rounds_over_2 + self.parameters.partial_rounds) {
-            self.apply_ark(&mut state, i);
-            self.apply_s_box(&mut state, false);
-            self.apply_mds(&mut state);
-        }
+            for i in full_rounds_over_2..(full_rounds_over_2 + self.parameters.partial_rounds) {
+                self.apply_round(&mut state, i, false);
+            }
 
-        for i in (full_rounds_over_2 + self.parameters.partial_rounds)
-            ..(self.parameters.partial_rounds + self.parameters.full_rounds)
-        {
-            self.apply_ark(&mut state, i);
-            self.apply_s_box(&mut state, true);
-            self.apply_mds(&mut state);
-        }
+            for i in (full_rounds_over_2 + self.parameters.partial_rounds)
+                ..(self.parameters.partial_rounds + self.parameters.full_rounds)
+            {
+                self.apply_round(&mut state, i, true);
+            }
+        });
         self.state = state;
     }
 
@@ -229,7 +235,7 @@ impl<F: PrimeField> PoseidonSponge<F> {
 
 impl<F: PrimeField> PoseidonSponge<F> {
     pub fn new(cs: CSRef<F>, parameters: &PoseidonConfig<F>) -> Self {
-        let state = vec![cs.one() * 0u32; parameters.rate + parameters.capacity];
+        let state = vec![V::zero(); parameters.rate + parameters.capacity];
         let mode = DuplexSpongeMode::Absorbing {
             next_absorb_index: 0,
         };
@@ -277,7 +283,7 @@ impl<F: PrimeField> PoseidonSponge<F> {
         };
     }
     pub fn squeeze_native_field_elements(&mut self, num_elements: usize) -> Vec<V<F>> {
-        let mut squeezed_elems = vec![self.cs.one() * 0u32; num_elements];
+        let mut squeezed_elems = vec![V::zero(); num_elements];
         match self.mode {
             DuplexSpongeMode::Absorbing {
                 next_absorb_index: _,
@@ -345,6 +351,7 @@ mod tests {
     use ark_ff::PrimeField;
     use crate::{CS, variables::Mode};
     use super::{PoseidonSponge as CWPoseidonSponge, circom_bn254_poseidon_canonical_config};
+    use crate::test_utils::test_hash;
 
     /// This Poseidon configuration generator produces a Poseidon configuration with custom parameters
     pub fn poseidon_custom_config<F: PrimeField>(
@@ -399,6 +406,28 @@ mod tests {
         }
         let cw_hash = sponge.squeeze_native_field_elements(1)[0].clone();
 
-        assert_eq!(ark_hash, cw_hash.raw())
+        assert_eq!(ark_hash, cw_hash.raw());
+        assert_eq!(cs.check(), Ok(()));
+    }
+
+    #[test]
+    pub fn test_poseidon_constraint_count() {
+        let values: Vec<Fr> = (1..5).map(Fr::from).collect();
+
+        let mut sponge = ArkPoseidonSponge::<Fr>::new(&poseidon_canonical_config());
+        sponge.absorb(&values);
+        let ark_hash = sponge.squeeze_native_field_elements(1)[0];
+
+        let cs = CS::new_ref(Mode::Compile);
+        let config = circom_bn254_poseidon_canonical_config::<Fr>();
+        let cw_hash = test_hash(&cs, &[1, 2, 3, 4]);
+
+        assert_eq!(ark_hash, cw_hash.raw());
+        assert_eq!(cs.check(), Ok(()));
+        // 1回の置換で、S-box x^5 (3制約) が full round で t 個、partial round で1個
+        let t = config.rate + config.capacity;
+        let sboxes = config.full_rounds * t + config.partial_rounds;
+        assert_eq!(cs.shape().num_constraints, 3 * sboxes);
+        assert_eq!(cs.shape().num_witnesses, values.len() + 3 * sboxes);
     }
 }
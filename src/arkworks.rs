@@ -0,0 +1,114 @@
+use std::marker::PhantomData;
+
+use ark_ff::Field;
+use ark_relations::r1cs::{
+    ConstraintSynthesizer, ConstraintSystemRef as ArkCSRef, LinearCombination, SynthesisError,
+    Variable,
+};
+
+use crate::{
+    CSRef,
+    variables::{ConstraintSystemRef, Mode},
+};
+
+/// Adapter that lets a cswire circuit be used wherever arkworks expects a
+/// `ConstraintSynthesizer`, e.g. `ark-groth16`.
+///
+/// The closure is traced once in `Mode::Compile`, and the resulting R1CS and
+/// assignment are replayed into the arkworks constraint system.
+pub struct Circuit<F: Field, C: FnOnce(CSRef<F>)> {
+    circuit: C,
+    _field: PhantomData<F>,
+}
+
+impl<F: Field, C: FnOnce(CSRef<F>)> Circuit<F, C> {
+    pub fn new(circuit: C) -> Self {
+        Self {
+            circuit,
+            _field: PhantomData,
+        }
+    }
+}
+
+impl<F: Field, C: FnOnce(CSRef<F>)> ConstraintSynthesizer<F> for Circuit<F, C> {
+    fn generate_constraints(self, ark: ArkCSRef<F>) -> Result<(), SynthesisError> {
+        let cs = ConstraintSystemRef::new(Mode::Compile);
+        (self.circuit)(cs.clone());
+        let r1cs = cs.compile();
+        let (instance, witness) = cs.witnesses();
+
+        // z = (1, x, w) の各列に対応するarkworksの変数
+        let mut vars = vec![Variable::One];
+        for x in instance.into_iter().skip(1) {
+            vars.push(ark.new_input_variable(|| Ok(x))?);
+        }
+        for w in witness {
+            vars.push(ark.new_witness_variable(|| Ok(w))?);
+        }
+
+        let lc = |row: &[(usize, F)]| {
+            LinearCombination(row.iter().map(|(i, c)| (*c, vars[*i])).collect())
+        };
+        for i in 0..r1cs.num_constraints() {
+            ark.enforce_constraint(lc(&r1cs.a[i]), lc(&r1cs.b[i]), lc(&r1cs.c[i]))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{Bn254, Fr};
+    use ark_groth16::{Groth16, prepare_verifying_key};
+    use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystem as ArkCS};
+    use ark_std::test_rng;
+
+    use super::Circuit;
+    use crate::{CSRef, test_utils::test_hash};
+
+    fn poseidon(cs: CSRef<Fr>, preimage: u64, expected: Fr) {
+        let hash = cs.wire(test_hash(&cs, &[preimage]));
+        let out = cs.alloc_input(expected);
+        cs.anchor(hash - out);
+    }
+
+    fn hash(preimage: u64) -> Fr {
+        let cs = CSRef::<Fr>::new(crate::Mode::Run);
+        test_hash(&cs, &[preimage]).raw()
+    }
+
+    #[test]
+    fn test_synthesize_poseidon() {
+        let expected = hash(7);
+
+        let ark = ArkCS::<Fr>::new_ref();
+        Circuit::new(|cs| poseidon(cs, 7, expected))
+            .generate_constraints(ark.clone())
+            .unwrap();
+        assert!(ark.is_satisfied().unwrap());
+        assert_eq!(ark.num_instance_variables(), 2);
+
+        let ark = ArkCS::<Fr>::new_ref();
+        Circuit::new(|cs| poseidon(cs, 8, expected))
+            .generate_constraints(ark.clone())
+            .unwrap();
+        assert!(!ark.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn test_groth16_poseidon() {
+        let mut rng = test_rng();
+        let expected = hash(7);
+
+        let circuit = Circuit::new(|cs| poseidon(cs, 7, expected));
+        let pk = Groth16::<Bn254>::generate_random_parameters_with_reduction(circuit, &mut rng)
+            .unwrap();
+        let circuit = Circuit::new(|cs| poseidon(cs, 7, expected));
+        let proof = Groth16::<Bn254>::create_random_proof_with_reduction(circuit, &pk, &mut rng)
+            .unwrap();
+
+        let pvk = prepare_verifying_key(&pk.vk);
+        assert!(Groth16::<Bn254>::verify_proof(&pvk, &proof, &[expected]).unwrap());
+        assert!(!Groth16::<Bn254>::verify_proof(&pvk, &proof, &[expected + Fr::from(1)]).unwrap());
+    }
+}
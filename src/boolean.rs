@@ -0,0 +1,179 @@
+use std::ops::Not;
+
+use ark_ff::Field;
+
+use crate::variables::{ConstraintSystemRef, V, VV, Wire};
+
+/// A linear expression that is either 0 or 1.
+///
+/// Booleanity is enforced once, when the bit is allocated with
+/// [`ConstraintSystemRef::alloc_bool`]. `!b` stays linear, while `and`, `or` and
+/// `xor` produce a [`BoolVV`] that can be wired back into a `Bool` with one
+/// constraint and no further booleanity check.
+#[derive(Clone, Debug)]
+pub struct Bool<F: Field>(pub(crate) V<F>);
+
+/// A quadratic expression that is either 0 or 1.
+#[derive(Clone)]
+pub struct BoolVV<F: Field>(VV<F>);
+
+impl<F: Field> Bool<F> {
+    pub fn constant(b: bool) -> Self {
+        Bool(V::constant(F::from(b)))
+    }
+    pub fn raw(&self) -> F {
+        self.0.raw()
+    }
+    pub fn value(&self) -> bool {
+        !self.0.raw().is_zero()
+    }
+    /// `self * other`
+    pub fn and(&self, other: &Self) -> BoolVV<F> {
+        BoolVV(&self.0 * &other.0)
+    }
+    /// `self + other - self * other`
+    pub fn or(&self, other: &Self) -> BoolVV<F> {
+        BoolVV(-(&self.0 * &other.0) + &self.0 + &other.0)
+    }
+    /// `self + other - 2 * self * other`
+    pub fn xor(&self, other: &Self) -> BoolVV<F> {
+        BoolVV(-(&self.0 * &other.0 * 2u32) + &self.0 + &other.0)
+    }
+    /// `a` if `self` is true, else `b`. Computed as `self * (a - b) + b`.
+    pub fn select(&self, a: impl Into<V<F>>, b: impl Into<V<F>>) -> VV<F> {
+        let b = b.into();
+        &self.0 * (a.into() - &b) + b
+    }
+}
+
+impl<F: Field> BoolVV<F> {
+    pub fn raw(&self) -> F {
+        self.0.raw()
+    }
+    pub fn value(&self) -> bool {
+        !self.0.raw().is_zero()
+    }
+}
+
+impl<F: Field> Not for Bool<F> {
+    type Output = Bool<F>;
+    fn not(self) -> Bool<F> {
+        Bool(1 - self.0)
+    }
+}
+
+impl<F: Field> Not for &Bool<F> {
+    type Output = Bool<F>;
+    fn not(self) -> Bool<F> {
+        Bool(1 - &self.0)
+    }
+}
+
+impl<F: Field> Not for BoolVV<F> {
+    type Output = BoolVV<F>;
+    fn not(self) -> BoolVV<F> {
+        BoolVV(1 - self.0)
+    }
+}
+
+impl<F: Field> From<Bool<F>> for V<F> {
+    fn from(b: Bool<F>) -> Self {
+        b.0
+    }
+}
+
+impl<F: Field> From<&Bool<F>> for V<F> {
+    fn from(b: &Bool<F>) -> Self {
+        b.0.clone()
+    }
+}
+
+impl<F: Field> From<BoolVV<F>> for VV<F> {
+    fn from(b: BoolVV<F>) -> Self {
+        b.0
+    }
+}
+
+impl<F: Field> From<Bool<F>> for BoolVV<F> {
+    fn from(b: Bool<F>) -> Self {
+        BoolVV(b.0.into())
+    }
+}
+
+impl<F: Field> ConstraintSystemRef<F> {
+    /// Allocates a witness bit and anchors `b * b - b`.
+    #[track_caller]
+    pub fn alloc_bool(&self, b: bool) -> Bool<F> {
+        let w = self.alloc(b);
+        self.anchor(w * w - w);
+        Bool(w.into())
+    }
+
+    /// Wires the result of boolean operations. The result is boolean by
+    /// construction, so only the defining constraint is added.
+    #[track_caller]
+    pub fn wire_bool(&self, b: impl Into<BoolVV<F>>) -> Bool<F> {
+        let b: BoolVV<F> = b.into();
+        let w: Wire<F> = self.wire(b.0);
+        Bool(w.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+    use ark_ff::{One, Zero};
+
+    use super::Bool;
+    use crate::{CSRef, Mode};
+
+    #[test]
+    fn test_bool_ops() {
+        for (x, y) in [(false, false), (false, true), (true, false), (true, true)] {
+            let cs = CSRef::<Fr>::new(Mode::Compile);
+            let a = cs.alloc_bool(x);
+            let b = cs.alloc_bool(y);
+            assert_eq!((a.value(), b.value()), (x, y));
+
+            assert_eq!((!&a).value(), !x);
+            assert_eq!(a.and(&b).value(), x && y);
+            assert_eq!(a.or(&b).value(), x || y);
+            assert_eq!(a.xor(&b).value(), x ^ y);
+            assert_eq!((!a.and(&b)).value(), !(x && y));
+            let selected = a.select(cs.alloc(10), Bool::constant(true));
+            assert_eq!(selected.raw(), if x { Fr::from(10) } else { Fr::one() });
+
+            // 結果を配線しても制約は1つずつ
+            let and = cs.wire_bool(a.and(&b));
+            let or = cs.wire_bool(a.or(&b));
+            let xor = cs.wire_bool(a.xor(&b));
+            let nand = cs.wire_bool(!and.clone());
+            let any = cs.wire_bool(xor.or(&and));
+            assert_eq!(any.value(), or.value());
+            assert_eq!(nand.value(), !(x && y));
+            cs.wire(selected);
+
+            assert_eq!(cs.shape().num_constraints, 2 + 5 + 1);
+            assert_eq!(cs.check(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_bool_soundness() {
+        let cs = CSRef::<Fr>::new(Mode::Compile);
+        let a = cs.alloc_bool(true);
+        assert!(a.value());
+        assert!(!(!a).value());
+        assert_eq!(cs.shape().num_constraints, 1);
+
+        let r1cs = cs.compile();
+        let (x, w) = cs.witnesses();
+        let mut z = [x, w].concat();
+        for v in [Fr::zero(), Fr::one()] {
+            z[1] = v;
+            assert_eq!(r1cs.check(&z), Ok(()));
+        }
+        z[1] = Fr::from(2);
+        assert!(r1cs.check(&z).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use std::{fmt, panic::Location};
+
+use ark_ff::Field;
+
+use crate::{
+    CSRef,
+    r1cs::R1CS,
+    variables::{ConstraintSystemRef, DivisionByZero, Mode},
+};
+
+/// Size of a traced circuit. Every trace of the same circuit must agree on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Shape {
+    pub num_inputs: usize,
+    pub num_witnesses: usize,
+    pub num_constraints: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShapeMismatch {
+    pub expected: Shape,
+    pub found: Shape,
+}
+
+impl fmt::Display for ShapeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "circuit shape mismatch: expected {:?}, found {:?}",
+            self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for ShapeMismatch {}
+
+/// Why [`CompiledCircuit::run`] did not produce an assignment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RunError {
+    Shape(ShapeMismatch),
+    DivisionByZero(DivisionByZero),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Shape(e) => e.fmt(f),
+            RunError::DivisionByZero(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+impl From<ShapeMismatch> for RunError {
+    fn from(e: ShapeMismatch) -> Self {
+        RunError::Shape(e)
+    }
+}
+
+impl From<DivisionByZero> for RunError {
+    fn from(e: DivisionByZero) -> Self {
+        RunError::DivisionByZero(e)
+    }
+}
+
+/// An R1CS compiled once and reused to produce assignments in `Mode::Run`.
+#[derive(Clone, Debug)]
+pub struct CompiledCircuit<F: Field> {
+    pub r1cs: R1CS<F>,
+    pub shape: Shape,
+}
+
+impl<F: Field> CompiledCircuit<F> {
+    /// Traces `circuit` in `Mode::Compile`.
+    pub fn compile(circuit: impl FnOnce(CSRef<F>)) -> Self {
+        let cs = ConstraintSystemRef::new(Mode::Compile);
+        circuit(cs.clone());
+        Self {
+            r1cs: cs.compile(),
+            shape: cs.shape(),
+        }
+    }
+
+    /// Traces `circuit` in `Mode::Run` and returns `(instance, witness)`.
+    ///
+    /// Fails if the trace allocated a different number of wires or constraints than
+    /// the compiled one, in which case the assignment would not fit the R1CS, or if
+    /// it divided by zero, in which case the assignment would not satisfy it.
+    pub fn run(&self, circuit: impl FnOnce(CSRef<F>)) -> Result<(Vec<F>, Vec<F>), RunError> {
+        let cs = ConstraintSystemRef::new(Mode::Run);
+        circuit(cs.clone());
+        let found = cs.shape();
+        if found != self.shape {
+            return Err(ShapeMismatch {
+                expected: self.shape,
+                found,
+            }
+            .into());
+        }
+        match cs.division_by_zero() {
+            Some(e) => Err(e.into()),
+            None => Ok(cs.witnesses()),
+        }
+    }
+}
+
+/// First constraint whose structure differs between two traces of a circuit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub index: usize,
+    /// Where the constraint was recorded in each trace. `None` if that trace ended
+    /// before `index`.
+    pub sites: [Option<&'static Location<'static>>; 2],
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let site = |s: Option<&Location>| s.map_or("<none>".to_string(), |s| s.to_string());
+        write!(
+            f,
+            "constraint {} diverges: {} vs {}",
+            self.index,
+            site(self.sites[0]),
+            site(self.sites[1])
+        )
+    }
+}
+
+/// Traces `circuit` in `Mode::Compile` on two inputs and reports the first
+/// constraint whose structure differs, which means the circuit branches on
+/// witness values.
+///
+/// If every constraint matches but the traces allocate a different number of wires,
+/// the divergence is reported at the end of the constraint list.
+pub fn find_divergence<F: Field, I>(
+    circuit: impl Fn(CSRef<F>, I),
+    a: I,
+    b: I,
+) -> Option<Divergence> {
+    let trace = |input| {
+        let cs = ConstraintSystemRef::new(Mode::Compile);
+        circuit(cs.clone(), input);
+        cs
+    };
+    let (a, b) = (trace(a), trace(b));
+    let (ha, hb) = (a.structure(), b.structure());
+    let index = match ha.iter().zip(&hb).position(|(x, y)| x != y) {
+        Some(index) => index,
+        None if ha.len() != hb.len() => ha.len().min(hb.len()),
+        None if a.shape() != b.shape() => ha.len(),
+        None => return None,
+    };
+    Some(Divergence {
+        index,
+        sites: [a.site(index), b.site(index)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use super::{CompiledCircuit, RunError, Shape, find_divergence};
+    use crate::{CSRef, test_utils::test_hash};
+
+    fn poseidon(cs: CSRef<Fr>, preimage: u64) {
+        let hash = test_hash(&cs, &[preimage]);
+        let out = cs.alloc_input(hash.raw());
+        cs.anchor(hash - out);
+    }
+
+    #[test]
+    fn test_replay() {
+        let circuit = CompiledCircuit::<Fr>::compile(|cs| poseidon(cs, 0));
+        for preimage in 1..4 {
+            let (x, w) = circuit.run(|cs| poseidon(cs, preimage)).unwrap();
+            assert_eq!(circuit.r1cs.check(&[x, w].concat()), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        // 値によって分岐する回路
+        let branching = |cs: CSRef<Fr>, x: u64| {
+            let a = cs.alloc(x);
+            if x > 10 {
+                cs.wire(a * a);
+            }
+        };
+        let circuit = CompiledCircuit::<Fr>::compile(|cs| branching(cs, 0));
+        assert!(circuit.run(|cs| branching(cs, 5)).is_ok());
+
+        let Err(RunError::Shape(err)) = circuit.run(|cs| branching(cs, 20)) else {
+            panic!("shape mismatch expected");
+        };
+        let shape = |num_witnesses, num_constraints| Shape {
+            num_inputs: 0,
+            num_witnesses,
+            num_constraints,
+        };
+        assert_eq!((err.expected, err.found), (shape(1, 0), shape(2, 1)));
+    }
+
+    #[test]
+    fn test_run_division_by_zero() {
+        let circuit = |cs: CSRef<Fr>, x: u64| {
+            let (a, b) = (cs.alloc(12), cs.alloc(x));
+            cs.wire(a / b);
+        };
+        let compiled = CompiledCircuit::<Fr>::compile(|cs| circuit(cs, 3));
+        let (x, w) = compiled.run(|cs| circuit(cs, 4)).unwrap();
+        assert_eq!(compiled.r1cs.check(&[x, w].concat()), Ok(()));
+
+        let Err(RunError::DivisionByZero(err)) = compiled.run(|cs| circuit(cs, 0)) else {
+            panic!("division by zero expected");
+        };
+        assert!(err.site.file().ends_with("circuit.rs"));
+    }
+
+    #[test]
+    fn test_find_divergence() {
+        let circuit = |cs: CSRef<Fr>, x: u64| {
+            let a = cs.alloc(x);
+            let b = cs.wire(a * a);
+            // 値による分岐
+            match x % 2 {
+                0 => cs.anchor(b - a * a),
+                _ => cs.anchor(a * a - b),
+            }
+            cs.wire(a + b);
+        };
+        assert_eq!(find_divergence(circuit, 2, 4), None);
+        assert_eq!(find_divergence(poseidon, 1, 2), None);
+
+        let div = find_divergence(circuit, 2, 3).unwrap();
+        assert_eq!(div.index, 1);
+        let [a, b] = div.sites.map(Option::unwrap);
+        assert!(a.file().ends_with("circuit.rs"));
+        assert_eq!(b.line(), a.line() + 1);
+
+        // 制約は同じでもwireの数が違う
+        let alloc = |cs: CSRef<Fr>, x: u64| {
+            (0..x).for_each(|i| {
+                cs.alloc(i);
+            })
+        };
+        let div = find_divergence(alloc, 1, 2).unwrap();
+        assert_eq!((div.index, div.sites), (0, [None, None]));
+    }
+}
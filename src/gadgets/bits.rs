@@ -0,0 +1,128 @@
+use ark_ff::{BigInteger, PrimeField};
+
+use crate::{
+    CSRef,
+    boolean::Bool,
+    variables::{Const, V, Wire},
+};
+
+// vを n ビットに分解し、ビットの和がvに等しいことをanchorする
+fn decompose<F: PrimeField>(cs: &CSRef<F>, v: V<F>, n: usize) -> Vec<Bool<F>> {
+    assert!(
+        n < F::MODULUS_BIT_SIZE as usize,
+        "{n} bits may wrap around the field modulus"
+    );
+    let bits = v.raw().into_bigint().to_bits_le();
+    let bits: Vec<_> = (0..n)
+        .map(|i| cs.alloc_bool(bits.get(i).copied().unwrap_or(false)))
+        .collect();
+    cs.anchor(v - from_bits_le(&bits));
+    bits
+}
+
+/// Decomposes `v` into `n` little-endian bits.
+///
+/// Costs `n + 1` constraints. The trace is unsatisfiable if `v >= 2^n`.
+pub fn to_bits_le<F: PrimeField>(cs: CSRef<F>, v: &Wire<F>, n: usize) -> Vec<Bool<F>> {
+    cs.namespace("to_bits_le", |cs| decompose(&cs, v.into(), n))
+}
+
+/// `Σ bits[i] * 2^i`. Linear, so it costs nothing.
+pub fn from_bits_le<F: PrimeField>(bits: &[Bool<F>]) -> V<F> {
+    let mut coeff = F::ONE;
+    let mut sum = V::zero();
+    for bit in bits {
+        sum += V::from(bit) * Const(coeff);
+        coeff.double_in_place();
+    }
+    sum
+}
+
+/// Enforces `v < 2^n` with `n + 1` constraints.
+pub fn range_check<F: PrimeField>(cs: CSRef<F>, v: &Wire<F>, n: usize) {
+    cs.namespace("range_check", |cs| {
+        decompose(&cs, v.into(), n);
+    })
+}
+
+/// Whether `a < b`, with `n + 2` constraints.
+///
+/// Both operands must already be known to be less than `2^n`, e.g. by
+/// [`range_check`]. Otherwise the result is meaningless.
+pub fn less_than<F: PrimeField>(cs: CSRef<F>, a: &Wire<F>, b: &Wire<F>, n: usize) -> Bool<F> {
+    cs.namespace("less_than", |cs| {
+        // a - b + 2^n は n+1 ビットに収まり、最上位ビットが a >= b を表す
+        let offset = F::from(2u32).pow([n as u64]);
+        let bits = decompose(&cs, a - b + Const(offset), n + 1);
+        !&bits[n]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use super::{from_bits_le, less_than, range_check, to_bits_le};
+    use crate::{CSRef, Mode, r1cs::CheckError};
+
+    #[test]
+    fn test_to_bits_le() {
+        let cs = CSRef::<Fr>::new(Mode::Compile);
+        let a = cs.alloc(0b1011u32);
+        let bits = to_bits_le(cs.clone(), &a, 6);
+        let values: Vec<_> = bits.iter().map(|b| b.value()).collect();
+        assert_eq!(values, [true, true, false, true, false, false]);
+        assert_eq!(from_bits_le(&bits).raw(), a.raw());
+        assert_eq!(cs.shape().num_constraints, 6 + 1);
+        assert_eq!(cs.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_range_check() {
+        for (value, n, ok) in [(0u64, 8, true), (255, 8, true), (256, 8, false), (1 << 40, 32, false)] {
+            let cs = CSRef::<Fr>::new(Mode::Compile);
+            let a = cs.alloc(value);
+            range_check(cs.clone(), &a, n);
+            assert_eq!(cs.shape().num_constraints, n + 1);
+            match cs.check() {
+                Ok(()) => assert!(ok, "{value} < 2^{n}"),
+                // 和の制約で検出される
+                Err(CheckError::Unsatisfied(e)) => {
+                    assert!(!ok, "{value} >= 2^{n}");
+                    assert_eq!((e.path.as_str(), e.index), ("range_check", n));
+                }
+                Err(e) => panic!("{e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_check_tampered_bit() {
+        let cs = CSRef::<Fr>::new(Mode::Compile);
+        let a = cs.alloc(6u32);
+        range_check(cs.clone(), &a, 3);
+        let r1cs = cs.compile();
+        let (x, w) = cs.witnesses();
+        let mut z = [x, w].concat();
+        assert_eq!(r1cs.check(&z), Ok(()));
+
+        // ビットでない値で和を合わせても、ブール制約で検出される (6 = 2 * 1 + 2 * 2)
+        z[2..5].copy_from_slice(&[Fr::from(2), Fr::from(2), Fr::from(0)]);
+        let Err(CheckError::Unsatisfied(e)) = r1cs.check(&z) else {
+            panic!("booleanity violation expected");
+        };
+        assert_eq!(e.index, 0);
+    }
+
+    #[test]
+    fn test_less_than() {
+        for (a, b) in [(3u64, 5u64), (5, 3), (4, 4), (0, 255), (255, 0)] {
+            let cs = CSRef::<Fr>::new(Mode::Compile);
+            let (x, y) = (cs.alloc(a), cs.alloc(b));
+            let lt = less_than(cs.clone(), &x, &y, 8);
+            assert_eq!(lt.value(), a < b, "{a} < {b}");
+            assert_eq!(cs.shape().num_constraints, 8 + 2);
+            assert_eq!(cs.check(), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use ark_ff::Field;
+
+use crate::{
+    CSRef,
+    boolean::Bool,
+    variables::{Const, V, Wire},
+};
+
+/// `a` if `cond` is true, else `b`, with one constraint.
+pub fn select<F: Field>(
+    cs: CSRef<F>,
+    cond: &Bool<F>,
+    a: impl Into<V<F>>,
+    b: impl Into<V<F>>,
+) -> V<F> {
+    cs.wire(cond.select(a, b)).into()
+}
+
+// inv = v⁻¹ をヒントとして割り当て、out = 1 - v * inv と v * out = 0 を課す。
+// v != 0 なら out = 0、v = 0 なら out = 1 に決まる
+fn is_zero_v<F: Field>(cs: &CSRef<F>, v: V<F>) -> Bool<F> {
+    let inv = cs.alloc(v.raw().inverse().unwrap_or_default());
+    let out = cs.wire(1 - &v * inv);
+    cs.anchor(v * out);
+    Bool(out.into())
+}
+
+/// Whether `v` is zero, with two constraints.
+pub fn is_zero<F: Field>(cs: CSRef<F>, v: &Wire<F>) -> Bool<F> {
+    cs.namespace("is_zero", |cs| is_zero_v(&cs, v.into()))
+}
+
+/// Whether `a == b`, with two constraints.
+pub fn is_equal<F: Field>(cs: CSRef<F>, a: impl Into<V<F>>, b: impl Into<V<F>>) -> Bool<F> {
+    cs.namespace("is_equal", |cs| is_zero_v(&cs, a.into() - b.into()))
+}
+
+/// Enforces `a != b` by wiring `1 / (a - b)`, one constraint.
+///
+/// If `a == b` the trace is unsatisfiable and reports a division by zero.
+pub fn enforce_not_equal<F: Field>(cs: CSRef<F>, a: impl Into<V<F>>, b: impl Into<V<F>>) {
+    cs.namespace("enforce_not_equal", |cs| {
+        cs.wire(Const(F::ONE) / (a.into() - b.into()));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use super::{enforce_not_equal, is_equal, is_zero, select};
+    use crate::{CSRef, Const, Mode, r1cs::CheckError};
+
+    #[test]
+    fn test_select() {
+        for cond in [false, true] {
+            let cs = CSRef::<Fr>::new(Mode::Compile);
+            let c = cs.alloc_bool(cond);
+            let (a, b) = (cs.alloc(3), cs.alloc(7));
+            let out = select(cs.clone(), &c, a, b + 1);
+            assert_eq!(out.raw(), Fr::from(if cond { 3 } else { 8 }));
+            assert_eq!(cs.shape().num_constraints, 1 + 1);
+            assert_eq!(cs.check(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_is_zero_and_is_equal() {
+        for (a, b) in [(0u32, 0u32), (5, 0), (5, 5), (2, 9)] {
+            let cs = CSRef::<Fr>::new(Mode::Compile);
+            let (x, y) = (cs.alloc(a), cs.alloc(b));
+            assert_eq!(is_zero(cs.clone(), &x).value(), a == 0);
+            assert_eq!(is_equal(cs.clone(), x, y).value(), a == b);
+            assert_eq!(is_equal(cs.clone(), x * 2u32, Const(Fr::from(10))).value(), a == 5);
+            assert_eq!(cs.shape().num_constraints, 3 * 2);
+            assert_eq!(cs.check(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_is_zero_soundness() {
+        for a in [0u32, 5] {
+            let cs = CSRef::<Fr>::new(Mode::Compile);
+            let x = cs.alloc(a);
+            is_zero(cs.clone(), &x);
+            let r1cs = cs.compile();
+            let (inst, wit) = cs.witnesses();
+            let mut z = [inst, wit].concat();
+            assert_eq!(r1cs.check(&z), Ok(()));
+
+            // z = (1, x, inv, out)。どのinvを選んでも結果は反転できない
+            z[3] = Fr::from(a != 0);
+            for inv in [Fr::from(0), Fr::from(1), z[2]] {
+                z[2] = inv;
+                assert!(r1cs.check(&z).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_enforce_not_equal() {
+        for (a, b, ok) in [(3u32, 4u32, true), (4, 4, false)] {
+            for mode in [Mode::Compile, Mode::Run] {
+                let cs = CSRef::<Fr>::new(mode);
+                let (x, y) = (cs.alloc(a), cs.alloc(b));
+                enforce_not_equal(cs.clone(), x, y);
+                assert_eq!(cs.shape().num_constraints, 1);
+                if let Mode::Compile = mode {
+                    match cs.check() {
+                        Ok(()) => assert!(ok),
+                        Err(CheckError::Unsatisfied(e)) => {
+                            assert_eq!((ok, e.path.as_str()), (false, "enforce_not_equal"))
+                        }
+                        Err(e) => panic!("{e}"),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod bits;
+pub mod cmp;
@@ -0,0 +1,424 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::{Add, Mul, Neg, Sub},
+    rc::Rc,
+};
+
+use ark_ff::Field;
+
+use crate::variables::{Exp, Idx, Node};
+
+const ONE: Idx = Idx::Input(0);
+
+/// Canonical linear combination `Σ coeff * z[idx]`.
+///
+/// Terms are sorted by index, duplicate indices are merged, zero coefficients are
+/// dropped and constants are folded into the one wire (`Idx::Input(0)`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinearCombination<F: Field>(Vec<(Idx, F)>);
+
+impl<F: Field> LinearCombination<F> {
+    pub fn zero() -> Self {
+        Self(vec![])
+    }
+
+    pub fn constant(c: F) -> Self {
+        Self::from((ONE, c))
+    }
+
+    pub fn terms(&self) -> &[(Idx, F)] {
+        &self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the value if this only refers to the one wire.
+    pub fn as_constant(&self) -> Option<F> {
+        match self.0.as_slice() {
+            [] => Some(F::ZERO),
+            [(ONE, c)] => Some(*c),
+            _ => None,
+        }
+    }
+
+    pub fn scale(mut self, c: F) -> Self {
+        if c.is_zero() {
+            return Self::zero();
+        }
+        self.0.iter_mut().for_each(|(_, v)| *v *= c);
+        self
+    }
+
+    /// Evaluates against a lookup of wire values.
+    pub fn evaluate(&self, value: impl Fn(Idx) -> F) -> F {
+        self.0.iter().map(|(i, c)| value(*i) * c).sum()
+    }
+}
+
+impl<F: Field> fmt::Display for LinearCombination<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "0");
+        }
+        for (i, (idx, c)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " + ")?;
+            }
+            match (*idx, c.is_one()) {
+                (ONE, _) => write!(f, "{c}")?,
+                (_, true) => write!(f, "{idx}")?,
+                (_, false) => write!(f, "{c}*{idx}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F: Field> From<Idx> for LinearCombination<F> {
+    fn from(idx: Idx) -> Self {
+        Self(vec![(idx, F::ONE)])
+    }
+}
+
+impl<F: Field> From<(Idx, F)> for LinearCombination<F> {
+    fn from((idx, c): (Idx, F)) -> Self {
+        match c.is_zero() {
+            true => Self::zero(),
+            false => Self(vec![(idx, c)]),
+        }
+    }
+}
+
+impl<F: Field> Add for LinearCombination<F> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        let (mut big, small) = match self.0.len() >= rhs.0.len() {
+            true => (self.0, rhs.0),
+            false => (rhs.0, self.0),
+        };
+        // 数項だけなら挿入する。長い和を1項ずつ積み上げる場合にマージだと二乗になる。
+        if small.len() <= 8 {
+            for (idx, c) in small {
+                match big.binary_search_by(|(i, _)| i.cmp(&idx)) {
+                    Ok(pos) => {
+                        big[pos].1 += c;
+                        if big[pos].1.is_zero() {
+                            big.remove(pos);
+                        }
+                    }
+                    Err(pos) => big.insert(pos, (idx, c)),
+                }
+            }
+            return Self(big);
+        }
+
+        // ソート済み同士のマージ
+        let mut out = Vec::with_capacity(big.len() + small.len());
+        let (mut x, mut y) = (big.into_iter().peekable(), small.into_iter().peekable());
+        loop {
+            let next = match (x.peek(), y.peek()) {
+                (Some(a), Some(b)) if a.0 == b.0 => {
+                    let (i, a) = x.next().unwrap();
+                    let (_, b) = y.next().unwrap();
+                    (i, a + b)
+                }
+                (Some(a), Some(b)) if a.0 < b.0 => x.next().unwrap(),
+                (Some(_), Some(_)) => y.next().unwrap(),
+                (Some(_), None) => x.next().unwrap(),
+                (None, Some(_)) => y.next().unwrap(),
+                (None, None) => break,
+            };
+            if !next.1.is_zero() {
+                out.push(next);
+            }
+        }
+        Self(out)
+    }
+}
+
+impl<F: Field> Neg for LinearCombination<F> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        self.scale(-F::ONE)
+    }
+}
+
+impl<F: Field> Sub for LinearCombination<F> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl<F: Field> Mul<F> for LinearCombination<F> {
+    type Output = Self;
+    fn mul(self, rhs: F) -> Self {
+        self.scale(rhs)
+    }
+}
+
+/// An expression in rank-1 form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Flat<F: Field> {
+    /// `lc`
+    Linear(LinearCombination<F>),
+    /// `a * b + c`
+    Quadratic {
+        a: LinearCombination<F>,
+        b: LinearCombination<F>,
+        c: LinearCombination<F>,
+    },
+}
+
+impl<F: Field> Flat<F> {
+    /// Splits into `(a, b, c)` with `exp = a * b + c`. A linear form has `b = 1`.
+    pub fn into_abc(
+        self,
+    ) -> (
+        LinearCombination<F>,
+        LinearCombination<F>,
+        LinearCombination<F>,
+    ) {
+        match self {
+            Flat::Linear(lc) => (
+                lc,
+                LinearCombination::constant(F::ONE),
+                LinearCombination::zero(),
+            ),
+            Flat::Quadratic { a, b, c } => (a, b, c),
+        }
+    }
+}
+
+// Σ products.0 * products.1 + lin
+#[derive(Clone)]
+struct Quad<F: Field> {
+    products: Vec<(LinearCombination<F>, LinearCombination<F>)>,
+    lin: LinearCombination<F>,
+}
+
+impl<F: Field> Quad<F> {
+    fn linear(lin: LinearCombination<F>) -> Self {
+        Self {
+            products: vec![],
+            lin,
+        }
+    }
+
+    fn scale(self, c: F) -> Self {
+        Self {
+            products: self
+                .products
+                .into_iter()
+                .map(|(a, b)| (a.scale(c), b))
+                .filter(|(a, _)| !a.is_zero())
+                .collect(),
+            lin: self.lin.scale(c),
+        }
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            products: [self.products, rhs.products].concat(),
+            lin: self.lin + rhs.lin,
+        }
+    }
+
+    fn mul(self, rhs: Self) -> Self {
+        let lhs_const = self.products.is_empty().then(|| self.lin.as_constant()).flatten();
+        let rhs_const = rhs.products.is_empty().then(|| rhs.lin.as_constant()).flatten();
+        match (lhs_const, rhs_const) {
+            (Some(c), _) => rhs.scale(c),
+            (_, Some(c)) => self.scale(c),
+            _ if self.products.is_empty() && rhs.products.is_empty() => Self {
+                products: vec![(self.lin, rhs.lin)],
+                lin: LinearCombination::zero(),
+            },
+            _ => panic!("degree of expression exceeds 2"),
+        }
+    }
+}
+
+/// Flattens expressions into rank-1 form.
+///
+/// The DAG is walked iteratively and the result of every shared node is memoized,
+/// so flattening many constraints that share subexpressions visits each node once.
+/// A memoized result is released as soon as all of its `Rc` owners have consumed it.
+/// Until then the memo keeps the node alive, so its address cannot be reused by a
+/// new node while the result is cached.
+pub struct Flattener<F: Field> {
+    memo: HashMap<*const Node<F>, Memo<F>>,
+}
+
+// 残りの参照数と結果。nodeを保持してアドレスの再利用を防ぐ
+struct Memo<F: Field> {
+    uses: usize,
+    _node: Rc<Node<F>>,
+    quad: Quad<F>,
+}
+
+impl<F: Field> Default for Flattener<F> {
+    fn default() -> Self {
+        Self {
+            memo: HashMap::new(),
+        }
+    }
+}
+
+impl<F: Field> Flattener<F> {
+    /// Panics if the expression has degree more than 2 or is a sum of several products,
+    /// neither of which fits in a single R1CS row.
+    pub fn flatten(&mut self, exp: &Exp<F>) -> Flat<F> {
+        let Quad { mut products, lin } = self.quad(exp);
+        match products.pop() {
+            None => Flat::Linear(lin),
+            Some((a, b)) if products.is_empty() => Flat::Quadratic { a, b, c: lin },
+            Some(_) => panic!("expression is not rank-1: sum of several products"),
+        }
+    }
+
+    fn quad(&mut self, exp: &Exp<F>) -> Quad<F> {
+        let root = match exp {
+            Exp::Node(node) => node,
+            leaf => return self.take(leaf),
+        };
+        // 後行順で子ノードから評価する
+        let mut stack = vec![(root, false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if self.memo.contains_key(&Rc::as_ptr(node)) {
+                continue;
+            }
+            let (x, y) = node.operands();
+            if !expanded {
+                stack.push((node, true));
+                for child in [y, x] {
+                    if let Exp::Node(child) = child {
+                        stack.push((child, false));
+                    }
+                }
+                continue;
+            }
+            let (x, y) = (self.take(x), self.take(y));
+            let quad = match node.as_ref() {
+                Node::Add(..) => x.add(y),
+                Node::Sub(..) => x.add(y.scale(-F::ONE)),
+                Node::Mul(..) => x.mul(y),
+            };
+            let memo = Memo {
+                uses: Rc::strong_count(node),
+                _node: node.clone(),
+                quad,
+            };
+            self.memo.insert(Rc::as_ptr(node), memo);
+        }
+        self.take(exp)
+    }
+
+    fn take(&mut self, exp: &Exp<F>) -> Quad<F> {
+        match exp {
+            Exp::Idx(idx) => Quad::linear((*idx).into()),
+            Exp::Coe(c) => Quad::linear(LinearCombination::constant(*c)),
+            Exp::Node(node) => {
+                let key = Rc::as_ptr(node);
+                let memo = self.memo.get_mut(&key).expect("node is flattened");
+                memo.uses -= 1;
+                match memo.uses {
+                    0 => self.memo.remove(&key).unwrap().quad,
+                    _ => memo.quad.clone(),
+                }
+            }
+        }
+    }
+}
+
+/// Flattens a single expression into rank-1 form. See [`Flattener::flatten`].
+pub fn flatten<F: Field>(exp: &Exp<F>) -> Flat<F> {
+    Flattener::default().flatten(exp)
+}
+
+/// Whether [`flatten`] accepts `exp`, i.e. it has at most one product after folding
+/// constants.
+pub fn is_rank_1<F: Field>(exp: &Exp<F>) -> bool {
+    Flattener::default().quad(exp).products.len() <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use std::rc::Rc;
+
+    use super::{Flat, Flattener, LinearCombination as LC};
+    use crate::variables::{ConstraintSystem, Exp, Idx, Mode, Node};
+
+    #[test]
+    fn test_lc_merge() {
+        let (a, b) = (Idx::Witness(0), Idx::Witness(1));
+        let x = LC::from(b) + LC::constant(Fr::from(2)) + LC::from(a) + LC::from(b);
+        assert_eq!(
+            x.terms(),
+            &[
+                (Idx::Input(0), Fr::from(2)),
+                (a, Fr::from(1)),
+                (b, Fr::from(2))
+            ]
+        );
+        assert_eq!((x.clone() - x).terms(), &[]);
+    }
+
+    #[test]
+    fn test_flatten() {
+        let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+        let a = cs.alloc(Fr::from(3));
+        let b = cs.alloc(Fr::from(4));
+        let (ia, ib) = (Idx::Witness(0), Idx::Witness(1));
+
+        let v = a + a + a - b * 2u32 + 4u32;
+        let lc =
+            LC::from((ia, Fr::from(3))) + LC::from((ib, -Fr::from(2))) + LC::constant(Fr::from(4));
+        assert_eq!(v.lc(), Some(lc));
+
+        let vv = (a + 1u32) * (b * 3u32) - a + 1u32;
+        let flat = Flat::Quadratic {
+            a: LC::from(ia) + LC::constant(Fr::from(1)),
+            b: LC::from((ib, Fr::from(3))),
+            c: LC::from((ia, -Fr::from(1))) + LC::constant(Fr::from(1)),
+        };
+        assert_eq!(vv.flatten(), Some(flat));
+
+        // 定数に畳み込まれる側はproductにならない
+        let vv = (a + b) * (a - a + 5u32);
+        assert_eq!(
+            vv.flatten(),
+            Some(Flat::Linear((LC::from(ia) + LC::from(ib)) * Fr::from(5)))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not rank-1")]
+    fn test_flatten_sum_of_products() {
+        let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+        let a = cs.alloc(Fr::from(3));
+        let b = cs.alloc(Fr::from(4));
+        let _ = (a * b + b * a).flatten();
+    }
+
+    #[test]
+    fn test_flattener_reused_across_dropped_nodes() {
+        // 外部に所有者が残るノードはmemoに残る。解放後に同じアドレスの
+        // 新しいノードが古い結果を受け取ってはいけない
+        let mut flattener = Flattener::<Fr>::default();
+        for i in 0..1000 {
+            let inner = Rc::new(Node::Add(Exp::Idx(Idx::Witness(i)), Exp::Coe(Fr::from(1))));
+            let outer = Exp::Node(Rc::new(Node::Mul(
+                Exp::Node(inner.clone()),
+                Exp::Coe(Fr::from(2)),
+            )));
+            let lc = (LC::from(Idx::Witness(i)) + LC::constant(Fr::from(1))) * Fr::from(2);
+            assert_eq!(flattener.flatten(&outer), Flat::Linear(lc), "iteration {i}");
+        }
+    }
+}
@@ -1,11 +1,31 @@
 #[cfg(test)]
 mod tests;
+#[cfg(test)]
+mod proptests;
+#[cfg(test)]
+mod test_utils;
 
 pub mod variables;
+pub mod r1cs;
+pub mod lc;
+pub mod circuit;
 pub mod ark_poseidon;
 pub mod utils;
+pub mod profile;
+pub mod snapshot;
+pub mod boolean;
+pub mod gadgets;
+pub mod lookup;
+#[cfg(feature = "arkworks")]
+pub mod arkworks;
 
-pub use variables::{ConstraintSystem, ConstraintSystemRef, Mode, V, Wire};
+pub use boolean::{Bool, BoolVV};
+pub use circuit::{CompiledCircuit, RunError, find_divergence};
+pub use lc::LinearCombination;
+pub use lookup::{Challenge, Lookup, Table, sponge_challenge};
+pub use profile::{Cost, Profile};
+pub use r1cs::R1CS;
+pub use variables::{Const, ConstraintSystem, ConstraintSystemRef, DivisionByZero, Mode, Quotient, V, Wire};
 pub use utils::pow;
 pub type CS<F> = ConstraintSystem<F>;
 pub type CSRef<F> = ConstraintSystemRef<F>;
@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+
+use ark_ff::{BigInteger, PrimeField};
+
+use crate::{
+    CSRef,
+    ark_poseidon::PoseidonSponge,
+    variables::{Const, ConstraintSystemRef, V, Wire},
+};
+
+/// A fixed table of field elements that values can be looked up in.
+#[derive(Clone, Debug)]
+pub struct Table<F: PrimeField> {
+    values: Vec<F>,
+    index: HashMap<F, usize>,
+    /// `Some(k)` if the table is `0..2^k`.
+    bits: Option<usize>,
+}
+
+impl<F: PrimeField> Table<F> {
+    pub fn new(values: Vec<F>) -> Self {
+        let index = values.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        Self {
+            values,
+            index,
+            bits: None,
+        }
+    }
+
+    /// The table `0..2^bits`, used by [`Lookup::range_check`].
+    ///
+    /// Panics unless `1 <= bits < 64`.
+    pub fn range(bits: usize) -> Self {
+        assert!(
+            (1..64).contains(&bits),
+            "range table needs 1 to 63 bits, got {bits}"
+        );
+        Self {
+            bits: Some(bits),
+            ..Self::new((0..1u64 << bits).map(F::from).collect())
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn contains(&self, value: &F) -> bool {
+        self.index.contains_key(value)
+    }
+}
+
+/// Lookups into a fixed [`Table`], proven together by a log-derivative (LogUp)
+/// argument. Only available inside [`ConstraintSystemRef::with_lookup`], which emits
+/// the argument when its closure returns, so no recorded lookup is left unenforced.
+///
+/// The argument allocates how often each table row was looked up and enforces
+///
+/// ```text
+/// Σ_i 1 / (α - v_i) = Σ_j m_j / (α - t_j)
+/// ```
+///
+/// at a challenge `α` that the prover must not know before the looked-up values and
+/// multiplicities are fixed. Each lookup costs one constraint, the table one per row,
+/// and the final comparison one more. Deriving `α` can cost far more:
+///
+/// * [`sponge_challenge`] squeezes it from a Poseidon sponge in the circuit, which is
+///   sound in plain R1CS. Absorbing takes a 300-constraint permutation per 4 values
+///   with the canonical BN254 config, about 76 constraints per lookup and per row,
+///   more than bit decomposition with
+///   [`gadgets::bits::range_check`](crate::gadgets::bits::range_check).
+/// * A proof system that commits to the witness before sampling challenges can supply
+///   `α` itself, for example as an input wire. Then a lookup costs one constraint,
+///   and the circuit relies on the proof system to derive `α` correctly.
+pub struct Lookup<F: PrimeField> {
+    cs: CSRef<F>,
+    table: Table<F>,
+    values: Vec<V<F>>,
+}
+
+impl<F: PrimeField> Lookup<F> {
+    fn new(cs: CSRef<F>, table: Table<F>) -> Self {
+        Self {
+            cs,
+            table,
+            values: vec![],
+        }
+    }
+
+    pub fn table(&self) -> &Table<F> {
+        &self.table
+    }
+
+    /// Records that `v` is in the table. It is enforced when the closure of
+    /// [`ConstraintSystemRef::with_lookup`] returns.
+    pub fn lookup(&mut self, v: impl Into<V<F>>) {
+        self.values.push(v.into());
+    }
+
+    /// Enforces `v < 2^n` by splitting `v` into `ceil(n / k)` limbs of the table's
+    /// width `k` and looking each one up. If `k` does not divide `n`, the top limb is
+    /// looked up a second time shifted by `k - n % k` bits. One constraint recombines
+    /// the limbs and every lookup costs what is described on [`Lookup`].
+    ///
+    /// Panics if the table was not built with [`Table::range`].
+    pub fn range_check(&mut self, v: &Wire<F>, n: usize) {
+        let k = self.table.bits.expect("range check needs a Table::range");
+        assert!(
+            n < F::MODULUS_BIT_SIZE as usize,
+            "{n} bits may wrap around the field modulus"
+        );
+        let bits = v.raw().into_bigint().to_bits_le();
+        let limb = |i: usize| {
+            let bits = bits.iter().skip(i * k).take(k.min(n - i * k));
+            F::from(bits.rev().fold(0u64, |acc, b| acc << 1 | *b as u64))
+        };
+        let limbs = (0..n.div_ceil(k)).map(limb).collect();
+        self.range_check_limbs(v, n, limbs);
+    }
+
+    // limbの値を外から与えられるようにして、不正なlimbでも健全なことをテストする
+    fn range_check_limbs(&mut self, v: &Wire<F>, n: usize, limbs: Vec<F>) {
+        let k = self.table.bits.expect("range check needs a Table::range");
+        let cs = self.cs.clone();
+        let limbs: Vec<_> = limbs.into_iter().map(|l| cs.alloc(l)).collect();
+        let mut sum = V::zero();
+        let mut coeff = F::ONE;
+        for limb in &limbs {
+            sum += limb * Const(coeff);
+            coeff *= F::from(1u64 << k);
+        }
+        cs.anchor(v - sum);
+
+        limbs.iter().for_each(|limb| self.lookup(limb));
+        // 最後のlimbが r < k ビットなら、それ自身と 2^(k-r) 倍の両方が表に収まることを見る。
+        // 倍した方だけでは t / 2^(k-r) のような体の元も通ってしまう
+        let r = n % k;
+        if let Some(last) = limbs.last().filter(|_| r != 0) {
+            self.lookup(last * Const(F::from(1u64 << (k - r))));
+        }
+    }
+
+    // 記録された全ての参照についてLogUpの等式を課す
+    fn finalize(self, challenge: impl Challenge<F>) {
+        let Self { cs, table, values } = self;
+        cs.namespace("lookup", |cs| {
+            let mut counts = vec![0u64; table.len()];
+            for v in &values {
+                if let Some(i) = table.index.get(&v.raw()) {
+                    counts[*i] += 1;
+                }
+            }
+            let multiplicities: Vec<_> = counts.iter().map(|m| cs.alloc(*m)).collect();
+            let alpha = challenge(&cs, &values, &multiplicities);
+
+            // αが表の値に一致するのは無視できる確率
+            let inverse = |num: V<F>, den: V<F>| -> V<F> { cs.wire(num / den).into() };
+            let lhs: Vec<_> = values
+                .iter()
+                .map(|v| inverse(V::constant(F::ONE), &alpha - v))
+                .collect();
+            let rhs: Vec<_> = multiplicities
+                .iter()
+                .zip(&table.values)
+                .map(|(m, t)| inverse(m.into(), &alpha - Const(*t)))
+                .collect();
+            cs.anchor(sum(lhs) - sum(rhs));
+        })
+    }
+}
+
+/// Source of the LogUp challenge `α`, called with the looked-up values and the
+/// multiplicity wires once they are allocated. Implemented by every such closure.
+pub trait Challenge<F: PrimeField>: FnOnce(&CSRef<F>, &[V<F>], &[Wire<F>]) -> V<F> {}
+
+impl<F: PrimeField, T: FnOnce(&CSRef<F>, &[V<F>], &[Wire<F>]) -> V<F>> Challenge<F> for T {}
+
+impl<F: PrimeField> ConstraintSystemRef<F> {
+    /// Runs `f` with a [`Lookup`] into `table`. When `f` returns, the multiplicities
+    /// are allocated, `challenge` is called with the looked-up values and the
+    /// multiplicity wires to get `α`, and the LogUp argument is enforced.
+    ///
+    /// `challenge` is [`sponge_challenge`] for plain R1CS, or a closure that returns
+    /// a challenge of the proof system, e.g. `|cs, _, _| cs.alloc_input(alpha).into()`.
+    /// See [`Lookup`] for what each choice costs.
+    pub fn with_lookup<R>(
+        &self,
+        table: Table<F>,
+        challenge: impl Challenge<F>,
+        f: impl FnOnce(&mut Lookup<F>) -> R,
+    ) -> R {
+        let mut lookup = Lookup::new(self.clone(), table);
+        let out = f(&mut lookup);
+        lookup.finalize(challenge);
+        out
+    }
+}
+
+/// A challenge for [`ConstraintSystemRef::with_lookup`] squeezed from `sponge` after
+/// absorbing the looked-up values and the multiplicities. `sponge` may already have
+/// absorbed other committed values of the circuit.
+pub fn sponge_challenge<F: PrimeField>(
+    sponge: &mut PoseidonSponge<F>,
+) -> impl Challenge<F> + '_ {
+    move |_, values, multiplicities| {
+        // 挑戦値は参照された値と多重度の全てに依存する
+        sponge.absorb(values);
+        sponge.absorb(&multiplicities.iter().map(V::from).collect::<Vec<_>>());
+        sponge.squeeze_native_field_elements(1).remove(0)
+    }
+}
+
+// 深い式の連鎖を避けるため二分木で足す
+fn sum<F: PrimeField>(mut terms: Vec<V<F>>) -> V<F> {
+    while terms.len() > 1 {
+        terms = terms
+            .chunks(2)
+            .map(|pair| pair.iter().cloned().sum())
+            .collect();
+    }
+    terms.pop().unwrap_or_else(V::zero)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+    use ark_ff::{Field, UniformRand};
+    use ark_std::test_rng;
+
+    use super::{Lookup, Table, sponge_challenge};
+    use crate::{CSRef, Mode, test_utils::test_sponge};
+
+    // αを回路内のスポンジから得るか、証明系から入力として受け取る
+    fn with_lookup<R>(
+        cs: &CSRef<Fr>,
+        external: bool,
+        table: Table<Fr>,
+        f: impl FnOnce(&mut Lookup<Fr>) -> R,
+    ) -> R {
+        match external {
+            false => cs.with_lookup(table, sponge_challenge(&mut test_sponge(cs, &[])), f),
+            true => cs.with_lookup(
+                table,
+                |cs, _, _| cs.alloc_input(Fr::rand(&mut test_rng())).into(),
+                f,
+            ),
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        for external in [false, true] {
+            for (values, ok) in [(vec![5u64, 5, 7, 3], true), (vec![5, 4], false)] {
+                let cs = CSRef::<Fr>::new(Mode::Compile);
+                let table = Table::new([3u64, 5, 7].map(Fr::from).to_vec());
+                assert!(table.contains(&Fr::from(7)) && !table.contains(&Fr::from(4)));
+                with_lookup(&cs, external, table, |lookup| {
+                    for v in values {
+                        lookup.lookup(cs.alloc(v));
+                    }
+                });
+                assert_eq!(cs.check().is_ok(), ok);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_range_check() {
+        // 4ビットのlimbで 12 ビットと 10 ビットと 0 ビットを見る
+        for (value, n, ok) in [
+            (0u64, 12usize, true),
+            ((1 << 12) - 1, 12, true),
+            (1 << 12, 12, false),
+            ((1 << 10) - 1, 10, true),
+            (1 << 10, 10, false),
+            (1 << 40, 10, false),
+            (0, 0, true),
+            (1, 0, false),
+        ] {
+            for external in [false, true] {
+                let cs = CSRef::<Fr>::new(Mode::Compile);
+                let limbs = n.div_ceil(4);
+                let lookups = limbs + (n % 4 != 0) as usize;
+                with_lookup(&cs, external, Table::range(4), |lookup| {
+                    let v = cs.alloc(value);
+                    lookup.range_check(&v, n);
+                    assert_eq!(cs.shape().num_constraints, 1);
+                    assert_eq!(cs.shape().num_witnesses, 1 + limbs);
+                    assert_eq!(lookup.values.len(), lookups);
+                });
+                assert_eq!(cs.check().is_ok(), ok, "{value} < 2^{n}");
+
+                // 参照と表の行ごとに1制約と合計の比較。回路内のαは全てを吸収する
+                let profile = cs.profile();
+                let lookup = profile.root.get("lookup").unwrap().cost.constraints;
+                let poseidon = profile.root.get("lookup/poseidon").map(|p| p.cost.constraints);
+                assert_eq!(lookup - poseidon.unwrap_or(0), lookups + 16 + 1);
+                let absorbed = (!external).then(|| 300 * (lookups + 16).div_ceil(4));
+                assert_eq!(poseidon, absorbed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_range_check_malicious_limbs() {
+        // 1215 = 15 + 15 * 16 + (15 / 4) * 256。シフトした最上位limbだけなら表に収まる
+        let quarter = Fr::from(4).inverse().unwrap();
+        for (value, n, limbs) in [
+            (Fr::from(1215), 10, vec![Fr::from(15), Fr::from(15), Fr::from(15) * quarter]),
+            (quarter, 2, vec![quarter]),
+        ] {
+            for external in [false, true] {
+                let cs = CSRef::<Fr>::new(Mode::Compile);
+                with_lookup(&cs, external, Table::range(4), |lookup| {
+                    let v = cs.alloc(value);
+                    lookup.range_check_limbs(&v, n, limbs.clone());
+                });
+                assert!(cs.check().is_err());
+
+                let r1cs = cs.compile();
+                let (x, w) = cs.witnesses();
+                assert!(r1cs.check(&[x, w].concat()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_64_bit_limbs() {
+        let range_check = |cs: &CSRef<Fr>, external: bool| {
+            with_lookup(cs, external, Table::range(8), |lookup| {
+                let registers: Vec<_> = (0..8u64).map(|i| cs.alloc(u64::MAX - i)).collect();
+                registers.iter().for_each(|r| lookup.range_check(r, 64));
+                assert_eq!(lookup.values.len(), 8 * 8);
+            });
+            cs.shape().num_constraints
+        };
+
+        // limbの合成、参照と表の行ごとに1制約、合計の比較、それに64 + 256個の値の吸収。
+        // 64ビットのレジスタ1つあたりではビット分解 (65制約) よりずっと高い
+        let cs = CSRef::<Fr>::new(Mode::Compile);
+        let absorbed = 8 * 8 + 256;
+        assert_eq!(range_check(&cs, false), 8 + 8 * 8 + 256 + 1 + 300 * absorbed / 4);
+        assert_eq!(cs.check(), Ok(()));
+
+        // αを証明系から受け取れば、レジスタ1つあたり合成と8つの参照だけ
+        let cs = CSRef::<Fr>::new(Mode::Compile);
+        assert_eq!(range_check(&cs, true), 8 * (1 + 8) + 256 + 1);
+        assert_eq!(cs.check(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "1 to 63 bits")]
+    fn test_table_range_too_wide() {
+        Table::<Fr>::range(64);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{fmt, ops::AddAssign, panic::Location};
+
+/// Size of a part of a circuit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cost {
+    pub constraints: usize,
+    /// Non-zero entries in the A, B and C rows of the constraints.
+    pub non_zeros: usize,
+    pub wires: usize,
+}
+
+impl AddAssign for Cost {
+    fn add_assign(&mut self, rhs: Self) {
+        self.constraints += rhs.constraints;
+        self.non_zeros += rhs.non_zeros;
+        self.wires += rhs.wires;
+    }
+}
+
+/// Cost of a namespace, including everything nested in it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scope {
+    pub name: String,
+    pub cost: Cost,
+    /// Most expensive first.
+    pub children: Vec<Scope>,
+}
+
+impl Scope {
+    /// Looks up a nested namespace by its `/`-separated path.
+    pub fn get(&self, path: &str) -> Option<&Scope> {
+        path.split('/').try_fold(self, |scope, name| {
+            scope.children.iter().find(|c| c.name == name)
+        })
+    }
+
+    fn insert(&mut self, path: &str, cost: Cost) {
+        self.cost += cost;
+        let Some(path) = (!path.is_empty()).then_some(path) else {
+            return;
+        };
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+        let pos = match self.children.iter().position(|c| c.name == name) {
+            Some(pos) => pos,
+            None => {
+                self.children.push(Scope {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.children.len() - 1
+            }
+        };
+        self.children[pos].insert(rest, cost);
+    }
+
+    // 安定ソートなので同じコストのラウンドは記録順のまま
+    fn sort(&mut self) {
+        self.children.sort_by_key(|c| std::cmp::Reverse(c.cost));
+        self.children.iter_mut().for_each(Scope::sort);
+    }
+
+    fn fmt_tree(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        let name = match depth {
+            0 => "total".to_string(),
+            _ => format!("{:indent$}{}", "", self.name, indent = depth * 2),
+        };
+        writeln!(f, "{name:<40} {}", Row(self.cost))?;
+        self.children
+            .iter()
+            .try_for_each(|c| c.fmt_tree(f, depth + 1))
+    }
+}
+
+/// Wires, constraints and non-zero matrix entries of a compiled circuit, aggregated
+/// per namespace and per call site of `alloc`, `wire` and `anchor`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The whole circuit. Top level wires and constraints only count here.
+    pub root: Scope,
+    /// Most expensive first.
+    pub sites: Vec<(&'static Location<'static>, Cost)>,
+}
+
+impl Profile {
+    pub(crate) fn new(
+        scopes: impl IntoIterator<Item = (impl AsRef<str>, Cost)>,
+        sites: impl IntoIterator<Item = (&'static Location<'static>, Cost)>,
+    ) -> Self {
+        let mut root = Scope::default();
+        for (path, cost) in scopes {
+            root.insert(path.as_ref(), cost);
+        }
+        root.sort();
+        let mut sites: Vec<_> = sites.into_iter().collect();
+        sites.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        Self { root, sites }
+    }
+
+    pub fn total(&self) -> Cost {
+        self.root.cost
+    }
+}
+
+struct Row(Cost);
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Cost {
+            constraints,
+            non_zeros,
+            wires,
+        } = self.0;
+        write!(f, "{constraints:>12} {non_zeros:>10} {wires:>8}")
+    }
+}
+
+const HEADER: &str = "constraints  non-zeros    wires";
+
+/// The namespace tree, one line per namespace.
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<40} {HEADER:>32}", "namespace")?;
+        self.fmt_tree(f, 0)
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.root)?;
+        writeln!(f, "{:<40} {HEADER:>32}", "call site")?;
+        for (site, cost) in &self.sites {
+            writeln!(f, "{:<40} {}", site.to_string(), Row(*cost))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use super::Cost;
+    use crate::{CSRef, Mode, test_utils::test_hash};
+
+    #[test]
+    fn test_profile() {
+        let cs = CSRef::<Fr>::new(Mode::Compile);
+        let x = cs.alloc(3);
+        let y = cs.namespace("square", |cs| cs.wire(x * x));
+        cs.namespace("square/check", |cs| cs.anchor(y - x * 3u32));
+        cs.anchor(y + x - 12u32);
+
+        let profile = cs.profile();
+        let cost = |constraints, non_zeros, wires| Cost {
+            constraints,
+            non_zeros,
+            wires,
+        };
+        assert_eq!(profile.total(), cost(3, 3 + 3 + 4, 2));
+        assert_eq!(profile.root.get("square").unwrap().cost, cost(2, 6, 1));
+        assert_eq!(profile.root.get("square/check").unwrap().cost, cost(1, 3, 0));
+        assert_eq!(profile.root.get("square/nothing"), None);
+
+        // 各制約とwireは1つの呼び出し元に数えられる
+        assert_eq!(profile.sites.len(), 4);
+        assert!(profile.sites.iter().all(|(s, _)| s.file().ends_with("profile.rs")));
+        let mut sum = Cost::default();
+        profile.sites.iter().for_each(|(_, c)| sum += *c);
+        assert_eq!(sum, profile.total());
+        let report = profile.to_string();
+        assert!(report.lines().nth(1).unwrap().starts_with("total "));
+        assert!(report.contains("\n    check "));
+    }
+
+    #[test]
+    fn test_profile_poseidon() {
+        let cs = CSRef::<Fr>::new(Mode::Compile);
+        test_hash(&cs, &[1]);
+
+        let profile = cs.profile();
+        let poseidon = profile.root.get("poseidon").unwrap();
+        assert_eq!(poseidon.cost.constraints, cs.shape().num_constraints);
+        // 幅5の状態で、x^5 は3つの制約。MDSは線形
+        let constraints = |path| poseidon.get(path).unwrap().cost.constraints;
+        assert_eq!(constraints("round_0/sbox"), 5 * 3);
+        assert_eq!(constraints("round_4/sbox"), 3);
+        assert_eq!(constraints("round_0/mds"), 0);
+        // フルラウンドが先頭に並ぶ
+        let full = |name: &str| ["round_0", "round_1", "round_2", "round_3"].contains(&name)
+            || ["round_64", "round_65", "round_66", "round_67"].contains(&name);
+        assert!(poseidon.children[..8].iter().all(|c| full(&c.name)));
+        assert!(!full(&poseidon.children[8].name));
+
+        assert!(profile.sites.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert!(profile.sites.iter().any(|(s, _)| s.file().ends_with("utils.rs")));
+    }
+}
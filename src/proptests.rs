@@ -0,0 +1,96 @@
+use ark_bn254::Fr;
+use proptest::prelude::*;
+
+use crate::variables::*;
+
+// ランダムな回路を組み立てるための命令列
+#[derive(Clone, Debug)]
+enum Op {
+    Input(u64),
+    Witness(u64),
+    // Σ coeff * wires[i] + constant
+    Linear(Vec<(usize, u64)>, u64),
+    // lhs * rhs + add
+    Quadratic(Vec<(usize, u64)>, Vec<(usize, u64)>, Vec<(usize, u64)>),
+    // anchor(lhs * rhs - wire(lhs * rhs))
+    Anchor(Vec<(usize, u64)>, Vec<(usize, u64)>),
+}
+
+fn terms() -> impl Strategy<Value = Vec<(usize, u64)>> {
+    prop::collection::vec((any::<usize>(), any::<u64>()), 1..4)
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        any::<u64>().prop_map(Op::Input),
+        any::<u64>().prop_map(Op::Witness),
+        (terms(), any::<u64>()).prop_map(|(t, c)| Op::Linear(t, c)),
+        (terms(), terms(), terms()).prop_map(|(a, b, c)| Op::Quadratic(a, b, c)),
+        (terms(), terms()).prop_map(|(a, b)| Op::Anchor(a, b)),
+    ]
+}
+
+fn linear(cs: &ConstraintSystemRef<Fr>, wires: &[Wire<Fr>], terms: &[(usize, u64)]) -> V<Fr> {
+    terms
+        .iter()
+        .map(|(i, c)| wires[i % wires.len()] * *c)
+        .fold(cs.one() * 0u32, |acc, x| acc + x)
+}
+
+fn build(cs: &ConstraintSystemRef<Fr>, ops: &[Op]) -> Vec<Wire<Fr>> {
+    let mut wires = vec![cs.one()];
+    for op in ops {
+        match op {
+            Op::Input(x) => wires.push(cs.alloc_input(*x)),
+            Op::Witness(x) => wires.push(cs.alloc_witness(*x)),
+            Op::Linear(t, c) => wires.push(cs.wire(linear(cs, &wires, t) + *c)),
+            Op::Quadratic(a, b, c) => {
+                let (a, b, c) = (linear(cs, &wires, a), linear(cs, &wires, b), linear(cs, &wires, c));
+                wires.push(cs.wire(a * b + c));
+            }
+            Op::Anchor(a, b) => {
+                let (a, b) = (linear(cs, &wires, a), linear(cs, &wires, b));
+                let w = cs.wire(&a * &b);
+                cs.anchor(a * b - w);
+                wires.push(w);
+            }
+        }
+    }
+    wires
+}
+
+proptest! {
+    #[test]
+    fn prop_wire_index_matches_witness(ops in prop::collection::vec(op(), 1..40)) {
+        let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+        let wires = build(&cs, &ops);
+
+        let r1cs = cs.compile();
+        let (x, w) = cs.witnesses();
+        let z = [x, w].concat();
+        prop_assert_eq!(z.len(), r1cs.num_wires);
+        for wire in &wires {
+            let col = wire.idx().unwrap().column(r1cs.num_inputs + 1);
+            prop_assert_eq!(z[col], wire.raw());
+        }
+        prop_assert_eq!(r1cs.check(&z), Ok(()));
+    }
+
+    #[test]
+    fn prop_tampered_wire_is_rejected(ops in prop::collection::vec(op(), 1..40), pick in any::<usize>()) {
+        let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+        build(&cs, &ops);
+
+        // cs.wireで定義された値を1つ書き換えると、その定義制約が破れる
+        let r1cs = cs.compile();
+        let defined: Vec<usize> = (0..r1cs.num_constraints())
+            .filter_map(|i| r1cs.c[i].iter().map(|(col, _)| *col).max())
+            .filter(|col| *col > r1cs.num_inputs)
+            .collect();
+        prop_assume!(!defined.is_empty());
+        let (x, w) = cs.witnesses();
+        let mut z = [x, w].concat();
+        z[defined[pick % defined.len()]] += Fr::from(1);
+        prop_assert!(r1cs.check(&z).is_err());
+    }
+}
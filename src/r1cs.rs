@@ -0,0 +1,158 @@
+use std::{fmt, panic::Location};
+
+use ark_ff::Field;
+
+use crate::{
+    lc::{Flattener, LinearCombination},
+    variables::{Exp, Idx},
+};
+
+/// Sparse matrix. Each row is a constraint, each entry is `(column, coefficient)`.
+pub type Matrix<F> = Vec<Vec<(usize, F)>>;
+
+/// Rank-1 constraint system `A·z ∘ B·z = C·z`.
+///
+/// Column `0` is the constant one wire, columns `1..=num_inputs` are public inputs
+/// and the remaining columns are private witnesses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct R1CS<F: Field> {
+    pub a: Matrix<F>,
+    pub b: Matrix<F>,
+    pub c: Matrix<F>,
+    pub num_inputs: usize,
+    pub num_wires: usize,
+}
+
+impl<F: Field> R1CS<F> {
+    pub fn new(num_inputs: usize, num_wires: usize) -> Self {
+        Self {
+            a: vec![],
+            b: vec![],
+            c: vec![],
+            num_inputs,
+            num_wires,
+        }
+    }
+
+    pub fn num_constraints(&self) -> usize {
+        self.a.len()
+    }
+
+    /// Evaluates every row against the full assignment `z` and reports the first
+    /// one where `A·z * B·z != C·z`, or that `z` does not have one value per column.
+    pub fn check(&self, z: &[F]) -> Result<(), CheckError<F>> {
+        if z.len() != self.num_wires {
+            return Err(CheckError::Length {
+                expected: self.num_wires,
+                found: z.len(),
+            });
+        }
+        self.check_rows(z).map_err(CheckError::Unsatisfied)
+    }
+
+    // zの長さは呼び出し側が保証する
+    pub(crate) fn check_rows(&self, z: &[F]) -> Result<(), Unsatisfied<F>> {
+        for i in 0..self.num_constraints() {
+            let (a, b, c) = (dot(&self.a[i], z), dot(&self.b[i], z), dot(&self.c[i], z));
+            if a * b != c {
+                return Err(Unsatisfied {
+                    index: i,
+                    a,
+                    b,
+                    c,
+                    site: None,
+                    path: String::new(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Lowers recorded expressions into rows.
+    /// `(Some(w), exp)` becomes `exp = w` and `(None, exp)` becomes `exp = 0`.
+    pub(crate) fn from_exprs(
+        exprs: &[(Option<Idx>, Exp<F>)],
+        num_inputs: usize,
+        num_witnesses: usize,
+    ) -> Self {
+        let num_instance = num_inputs + 1;
+        let mut r1cs = Self::new(num_inputs, num_instance + num_witnesses);
+        let row = |lc: LinearCombination<F>| -> Vec<(usize, F)> {
+            lc.terms()
+                .iter()
+                .map(|(i, c)| (i.column(num_instance), *c))
+                .collect()
+        };
+        let mut flattener = Flattener::default();
+        for (wire, exp) in exprs {
+            // a * b + c = w  =>  a * b = w - c
+            let (a, b, c) = flattener.flatten(exp).into_abc();
+            let w = wire.map(LinearCombination::from).unwrap_or_default();
+            r1cs.a.push(row(a));
+            r1cs.b.push(row(b));
+            r1cs.c.push(row(w - c));
+        }
+        r1cs
+    }
+}
+
+/// The first constraint whose evaluated sides do not satisfy `a * b = c`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Unsatisfied<F: Field> {
+    pub index: usize,
+    pub a: F,
+    pub b: F,
+    pub c: F,
+    /// Where the constraint was recorded, if known.
+    pub site: Option<&'static Location<'static>>,
+    /// Namespace the constraint was recorded in. Empty if unknown or top level.
+    pub path: String,
+}
+
+impl<F: Field> fmt::Display for Unsatisfied<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "constraint {}", self.index)?;
+        if let Some(site) = self.site {
+            write!(f, " at {site}")?;
+        }
+        write!(
+            f,
+            " unsatisfied: ({}) * ({}) != ({})",
+            self.a, self.b, self.c
+        )
+    }
+}
+
+/// Why [`R1CS::check`] or [`ConstraintSystemRef::check`] rejected an assignment.
+///
+/// [`ConstraintSystemRef::check`]: crate::ConstraintSystemRef::check
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckError<F: Field> {
+    /// `z` does not have `num_wires` entries.
+    Length { expected: usize, found: usize },
+    Unsatisfied(Unsatisfied<F>),
+    /// The trace ran in `Mode::Run`, which records no constraint to check.
+    NotRecorded,
+}
+
+impl<F: Field> fmt::Display for CheckError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Length { expected, found } => write!(
+                f,
+                "assignment has {found} values, expected {expected}"
+            ),
+            CheckError::Unsatisfied(e) => e.fmt(f),
+            CheckError::NotRecorded => {
+                write!(f, "constraints are not recorded in Mode::Run, nothing to check")
+            }
+        }
+    }
+}
+
+fn dot<F: Field>(row: &[(usize, F)], z: &[F]) -> F {
+    row.iter().map(|(i, c)| z[*i] * c).sum()
+}
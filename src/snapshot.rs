@@ -0,0 +1,140 @@
+use std::{env, fs, path::PathBuf};
+
+use ark_ff::Field;
+
+use crate::{CSRef, Mode};
+
+/// Set to `1` to write the current costs into the snapshot files instead of
+/// comparing against them.
+pub const UPDATE_ENV: &str = "CSWIRE_UPDATE_SNAPSHOTS";
+
+/// Compiles `circuit` and compares its cost per namespace (see
+/// [`ConstraintSystemRef::profile`](crate::ConstraintSystemRef::profile)) with the
+/// checked-in file `snapshots/{name}.txt` of the crate under test.
+///
+/// Panics with a line diff if the costs changed. Run the tests with
+/// `CSWIRE_UPDATE_SNAPSHOTS=1` to accept the change, and commit the updated file.
+#[track_caller]
+pub fn assert_snapshot<F: Field>(name: &str, circuit: impl FnOnce(CSRef<F>)) {
+    let cs = CSRef::new(Mode::Compile);
+    circuit(cs.clone());
+    let actual = cs.profile().root.to_string();
+
+    // cargo testはパッケージのルートで実行される
+    let dir = env::var_os("CARGO_MANIFEST_DIR").map_or_else(PathBuf::new, PathBuf::from);
+    let path = dir.join("snapshots").join(format!("{name}.txt"));
+    if env::var(UPDATE_ENV).is_ok_and(|v| v == "1") {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    match fs::read_to_string(&path) {
+        Ok(expected) if expected == actual => {}
+        Ok(expected) => panic!(
+            "snapshot {} changed, rerun with {UPDATE_ENV}=1 to accept:\n{}",
+            path.display(),
+            diff(&expected, &actual)
+        ),
+        Err(e) => panic!(
+            "snapshot {} cannot be read ({e}), rerun with {UPDATE_ENV}=1 to create it:\n{actual}",
+            path.display()
+        ),
+    }
+}
+
+// 最長共通部分列による行単位のdiff
+fn diff(expected: &str, actual: &str) -> String {
+    let (a, b): (Vec<_>, Vec<_>) = (expected.lines().collect(), actual.lines().collect());
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = match a[i] == b[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let line = match (a.get(i), b.get(j)) {
+            (Some(x), Some(y)) if x == y => {
+                (i, j) = (i + 1, j + 1);
+                format!(" {x}")
+            }
+            (Some(x), None) => {
+                i += 1;
+                format!("-{x}")
+            }
+            (Some(x), Some(_)) if lcs[i + 1][j] >= lcs[i][j + 1] => {
+                i += 1;
+                format!("-{x}")
+            }
+            (_, Some(y)) => {
+                j += 1;
+                format!("+{y}")
+            }
+            (None, None) => unreachable!(),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::Fr;
+
+    use super::{assert_snapshot, diff};
+    use crate::{pow, test_utils::test_sponge};
+
+    #[test]
+    fn test_diff() {
+        let expected = "a\nb\nc\nd\n";
+        let actual = "a\nc\nx\nd\ne\n";
+        assert_eq!(diff(expected, actual), " a\n-b\n c\n+x\n d\n+e\n");
+        assert_eq!(diff("", "a\n"), "+a\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be read")]
+    fn test_snapshot_missing() {
+        assert_snapshot::<Fr>("does_not_exist", |cs| {
+            cs.alloc(1);
+        });
+    }
+
+    #[test]
+    fn test_snapshot_pow() {
+        assert_snapshot::<Fr>("pow", |cs| {
+            let x = cs.alloc(3);
+            pow(cs.clone(), x.into(), 5);
+            pow(cs.clone(), x.into(), 255);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "-total                                              17")]
+    fn test_snapshot_changed() {
+        assert_snapshot::<Fr>("pow", |cs| {
+            let x = cs.alloc(3);
+            pow(cs.clone(), x.into(), 5);
+        });
+    }
+
+    #[test]
+    fn test_snapshot_poseidon_absorb() {
+        // rateを超えると吸収の途中で置換が走る
+        assert_snapshot::<Fr>("poseidon_absorb", |cs| {
+            test_sponge(&cs, &[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        });
+    }
+
+    #[test]
+    fn test_snapshot_poseidon_squeeze() {
+        assert_snapshot::<Fr>("poseidon_squeeze", |cs| {
+            test_sponge(&cs, &[0]).squeeze_native_field_elements(6);
+        });
+    }
+}
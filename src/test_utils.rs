@@ -0,0 +1,22 @@
+// テスト間で共有するフィクスチャ
+
+use ark_ff::PrimeField;
+
+use crate::{
+    CSRef,
+    ark_poseidon::{PoseidonSponge, circom_bn254_poseidon_canonical_config},
+    variables::V,
+};
+
+/// The canonical BN254 sponge after absorbing `inputs` as fresh witnesses.
+pub(crate) fn test_sponge<F: PrimeField>(cs: &CSRef<F>, inputs: &[u64]) -> PoseidonSponge<F> {
+    let mut sponge = PoseidonSponge::new(cs.clone(), &circom_bn254_poseidon_canonical_config());
+    let inputs: Vec<_> = inputs.iter().map(|i| cs.alloc(*i).into()).collect();
+    sponge.absorb(&inputs);
+    sponge
+}
+
+/// The hash of `inputs`, see [`test_sponge`].
+pub(crate) fn test_hash<F: PrimeField>(cs: &CSRef<F>, inputs: &[u64]) -> V<F> {
+    test_sponge(cs, inputs).squeeze_native_field_elements(1).remove(0)
+}
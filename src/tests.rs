@@ -1,7 +1,12 @@
+#![allow(clippy::op_ref, clippy::erasing_op, clippy::identity_op)]
+
 use ark_bn254::Fr;
-use ark_ff::UniformRand;
+use ark_ff::{BigInteger, Field, PrimeField, UniformRand};
 use ark_std::test_rng;
 
+use crate::gadgets::cmp::enforce_not_equal;
+use crate::lc::LinearCombination;
+use crate::r1cs::{CheckError, Matrix};
 use crate::variables::*;
 
 #[test]
@@ -101,6 +106,131 @@ fn test_mul_wire_v() {
     assert!(c==x.raw());
 }
 
+#[test]
+fn test_mul_vv_const() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let mut rng = test_rng();
+    let a = Fr::rand(&mut rng);
+    let b = Fr::rand(&mut rng);
+    let k = Fr::rand(&mut rng);
+    let c = a * b * k;
+
+    let a = cs.alloc(a);
+    let b = cs.alloc(b);
+    let ab = a * b;
+    let k = Const(k);
+
+    let x = ab.clone() * k;
+    assert!(c==x.raw());
+
+    let x = &ab * k;
+    assert!(c==x.raw());
+
+    let x = ab.clone() * &k;
+    assert!(c==x.raw());
+
+    let x = &ab * &k;
+    assert!(c==x.raw());
+
+    let x = k * ab.clone();
+    assert!(c==x.raw());
+
+    let x = &k * ab.clone();
+    assert!(c==x.raw());
+
+    let x = k * &ab;
+    assert!(c==x.raw());
+
+    let x = &k * &ab;
+    assert!(c==x.raw());
+
+    // 定数倍しても1つの制約に収まる
+    let w = cs.wire(k * (&ab * k) * 3u32 - a * k + 1u32);
+    assert_eq!(w.raw(), c * k.0 * Fr::from(3) - a.raw() * k.0 + Fr::from(1));
+    assert_eq!(cs.compile().num_constraints(), 1);
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_neg_wire() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let mut rng = test_rng();
+    let a = Fr::rand(&mut rng);
+    let c = -a;
+
+    let a = cs.alloc(a);
+
+    let x = -a;
+    assert!(c==x.raw());
+
+    let x = -&a;
+    assert!(c==x.raw());
+
+    let lc = LinearCombination::from((Idx::Witness(0), -Fr::from(1)));
+    assert_eq!((-a).lc(), Some(lc));
+    assert_eq!((-a + a).lc(), Some(LinearCombination::zero()));
+}
+
+#[test]
+fn test_neg_v() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let mut rng = test_rng();
+    let a = Fr::rand(&mut rng);
+    let b = Fr::rand(&mut rng);
+    let c = -(a + b);
+
+    let a = cs.alloc(a);
+    let b = cs.alloc(b);
+    let v = a + b;
+
+    let x = -v.clone();
+    assert!(c==x.raw());
+
+    let x = -&v;
+    assert!(c==x.raw());
+
+    let x = -(-&v);
+    assert!(-c==x.raw());
+    assert_eq!(x.lc(), v.lc());
+
+    // 定数の符号反転は畳み込まれる
+    assert_eq!((-V::constant(Fr::from(2))).as_constant(), Some(-Fr::from(2)));
+    assert_eq!(-Const(Fr::from(2)), Const(-Fr::from(2)));
+
+    // 符号反転したVも掛けられる
+    let w = cs.wire(-a * -&v);
+    assert_eq!(w.raw(), a.raw() * -c);
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_neg_vv() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let mut rng = test_rng();
+    let a = Fr::rand(&mut rng);
+    let b = Fr::rand(&mut rng);
+    let c = -(a * b);
+
+    let a = cs.alloc(a);
+    let b = cs.alloc(b);
+    let ab = a * b;
+
+    let x = -ab.clone();
+    assert!(c==x.raw());
+
+    let x = -&ab;
+    assert!(c==x.raw());
+
+    let x = -(-&ab);
+    assert!(-c==x.raw());
+
+    let w = cs.wire(-&ab + 1u32);
+    assert_eq!(w.raw(), c + Fr::from(1));
+    assert_eq!(cs.compile().num_constraints(), 1);
+    assert_eq!(cs.check(), Ok(()));
+    assert_eq!((-ab).flatten(), (a * b * Const(-Fr::from(1))).flatten());
+}
+
 #[test]
 pub fn test_sum_wire() {
 
@@ -112,3 +242,565 @@ pub fn test_sum_wire() {
     }).sum();
     assert!(sum.raw() == aaa)
 }
+
+fn dot(row: &[(usize, Fr)], z: &[Fr]) -> Fr {
+    row.iter().map(|(i, c)| z[*i] * c).sum()
+}
+
+#[test]
+fn test_compile_mul() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(11));
+    let b = cs.alloc(Fr::from(22));
+    let c = cs.wire(a * b - 1u64);
+    assert!(c.raw() == Fr::from(241));
+
+    let r1cs = cs.compile();
+    let one = Fr::from(1);
+    let a: Matrix<Fr> = vec![vec![(1, one)]];
+    let b: Matrix<Fr> = vec![vec![(2, one)]];
+    let c: Matrix<Fr> = vec![vec![(0, one), (3, one)]];
+    assert_eq!(r1cs.num_constraints(), 1);
+    assert_eq!(r1cs.num_wires, 4);
+    assert_eq!((r1cs.a, r1cs.b, r1cs.c), (a, b, c));
+}
+
+#[test]
+fn test_compile_satisfied() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(3));
+    let b = cs.alloc(Fr::from(5));
+    let c = cs.wire((a + 2u32) * (b - 1u32) + a);
+    let d = cs.wire(c * 3u32 - b);
+    cs.anchor(d - c * 3u32 + b);
+    cs.anchor(a * b - 15u32);
+
+    let r1cs = cs.compile();
+    let (x, w) = cs.witnesses();
+    let z = [x, w].concat();
+    assert_eq!(r1cs.num_constraints(), 4);
+    for i in 0..r1cs.num_constraints() {
+        let (a, b, c) = (dot(&r1cs.a[i], &z), dot(&r1cs.b[i], &z), dot(&r1cs.c[i], &z));
+        assert_eq!(a * b, c, "constraint {i}");
+    }
+}
+
+fn range_check(cs: &ConstraintSystemRef<Fr>, v: &Wire<Fr>, bit_range: usize, shift: usize) {
+    let one = cs.one();
+    let bits = v.raw().into_bigint().to_bits_le();
+    let bits: Vec<_> = bits[..bit_range].iter().map(|b| cs.alloc(*b)).collect();
+    bits.iter().for_each(|b| cs.anchor((one - b) * b));
+
+    let sum = (0..bit_range)
+        .map(|i| 1u64 << (i + shift))
+        .zip(bits)
+        .map(|(coeff, b)| b * coeff)
+        .sum::<V<Fr>>();
+
+    cs.anchor(v - sum);
+}
+
+#[test]
+fn test_check() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(1000));
+    range_check(&cs, &a, 16, 0);
+    assert_eq!(cs.check(), Ok(()));
+
+    // 係数を間違えたanchorは最後の制約で検出される
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(1000));
+    range_check(&cs, &a, 16, 1);
+    let Err(CheckError::Unsatisfied(err)) = cs.check() else {
+        panic!("unsatisfied constraint expected");
+    };
+    assert_eq!(err.index, 16);
+    assert_eq!((err.a, err.b, err.c), (-Fr::from(1000), Fr::from(1), Fr::from(0)));
+}
+
+#[test]
+fn test_check_wire() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(3));
+    let b = cs.alloc(Fr::from(4));
+    cs.wire(a * b);
+    cs.anchor(a + b - 7u32);
+    assert_eq!(cs.check(), Ok(()));
+
+    cs.anchor(a * b - 11u32);
+    let Err(CheckError::Unsatisfied(err)) = cs.check() else {
+        panic!("unsatisfied constraint expected");
+    };
+    assert_eq!(err.index, 2);
+}
+
+#[test]
+fn test_check_run_mode_and_length() {
+    // Mode::Runでは制約が記録されないので、間違ったanchorも含めて検査できない
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Run);
+    let a = cs.alloc(Fr::from(3));
+    cs.anchor(a - 4u32);
+    assert_eq!(cs.check(), Err(CheckError::NotRecorded));
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Run);
+    cs.anchor(cs.alloc(Fr::from(3)) - 3u32);
+    assert_eq!(cs.check(), Err(CheckError::NotRecorded));
+
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(3));
+    cs.anchor(a - 3u32);
+    let r1cs = cs.compile();
+    let err = r1cs.check(&[Fr::from(1)]).unwrap_err();
+    assert_eq!(err, CheckError::Length { expected: 2, found: 1 });
+    assert_eq!(err.to_string(), "assignment has 1 values, expected 2");
+}
+
+#[test]
+fn test_alloc_input() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc_witness(Fr::from(3));
+    let x = cs.alloc_input(Fr::from(12));
+    let b = cs.alloc(Fr::from(4));
+    let y = cs.alloc_input(Fr::from(13));
+    cs.anchor(a * b - x);
+    cs.anchor(x + 1u32 - y);
+
+    let (instance, witness) = cs.witnesses();
+    assert_eq!(instance, vec![Fr::from(1), Fr::from(12), Fr::from(13)]);
+    assert_eq!(witness, vec![Fr::from(3), Fr::from(4)]);
+
+    // z = (1, x, w)
+    let r1cs = cs.compile();
+    let one = Fr::from(1);
+    assert_eq!((r1cs.num_inputs, r1cs.num_wires), (2, 5));
+    assert_eq!(r1cs.a[0], vec![(3, one)]);
+    assert_eq!(r1cs.b[0], vec![(4, one)]);
+    assert_eq!(r1cs.c[0], vec![(1, one)]);
+    assert_eq!(r1cs.a[1], vec![(0, one), (1, one), (2, -one)]);
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_deep_expression() {
+    // 再帰的なdrop/走査ではスタックが溢れる深さ
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let n = 200_000u64;
+    let sum: V<Fr> = (0..n).map(|i| cs.alloc(i)).sum();
+    let total = cs.wire(sum);
+    assert_eq!(total.raw(), Fr::from(n * (n - 1) / 2));
+
+    let r1cs = cs.compile();
+    assert_eq!(r1cs.a[0].len(), n as usize);
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_shared_expression() {
+    // 木として展開すると2^64ノードになる式
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(3));
+    let mut x: V<Fr> = a + 1u32;
+    for _ in 0..64 {
+        x = &x + &x;
+    }
+    let y = cs.wire(&x * &x);
+    cs.anchor(&x - y * 0u32 - &x);
+
+    assert_eq!(cs.check(), Ok(()));
+    let coeff = Fr::from(2).pow([64]);
+    let r1cs = cs.compile();
+    assert_eq!(r1cs.a[0], vec![(0, coeff), (1, coeff)]);
+}
+
+#[test]
+fn test_run_mode_skips_expressions() {
+    use crate::test_utils::test_hash;
+
+    let trace = |mode| {
+        let cs = ConstraintSystem::<Fr>::new_ref(mode);
+        let hash = test_hash(&cs, &[0, 1, 2, 3, 4, 5]);
+        (cs, hash)
+    };
+    let (run, run_hash) = trace(Mode::Run);
+    let (compile, compile_hash) = trace(Mode::Compile);
+
+    assert_eq!(run.witnesses(), compile.witnesses());
+    assert_eq!(run_hash.raw(), compile_hash.raw());
+    assert!(run_hash.lc().is_none());
+    assert!(compile_hash.lc().is_some());
+    assert!(run.one().idx().is_none());
+}
+
+#[test]
+fn test_source_location() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let line = line!() + 1;
+    let a = cs.alloc(Fr::from(3));
+    let x = cs.alloc_input(Fr::from(13));
+    let b = cs.wire(a * a);
+    cs.anchor(b + a - x);
+
+    let site = |idx: Idx| cs.wire_site(idx).unwrap();
+    assert!(site(Idx::Witness(0)).file().ends_with("tests.rs"));
+    assert_eq!(site(Idx::Witness(0)).line(), line);
+    assert_eq!(site(Idx::Input(1)).line(), line + 1);
+    assert_eq!(site(Idx::Witness(1)).line(), line + 2);
+    assert_eq!(cs.site(0).unwrap().line(), line + 2);
+    assert_eq!(cs.site(1).unwrap().line(), line + 3);
+    assert_eq!(cs.wire_site(Idx::Input(0)), None);
+
+    let Err(CheckError::Unsatisfied(err)) = cs.check() else {
+        panic!("unsatisfied constraint expected");
+    };
+    assert_eq!(err.index, 1);
+    assert_eq!(err.site, cs.site(1));
+    assert!(err.to_string().contains(&format!("tests.rs:{}", line + 3)));
+
+    let dump = cs.dump();
+    assert!(dump.contains(&format!("w1 = 9  @ src/tests.rs:{}", line + 2)));
+    assert!(dump.contains("#0: (w0) * (w0) = (w1)"));
+    assert!(dump.contains(&format!(
+        "#1: ({}*x1 + w0 + w1) * (1) = (0)  @",
+        -Fr::from(1)
+    )));
+}
+
+#[test]
+fn test_set_mode() {
+    // 何も記録する前なら切り替えられる
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Run);
+    cs.set_mode(Mode::Compile);
+    let line = line!() + 1;
+    let a = cs.alloc(Fr::from(3));
+    assert_eq!(cs.wire_site(a.idx().unwrap()).unwrap().line(), line);
+}
+
+#[test]
+#[should_panic(expected = "set_mode after the trace has started: 0 inputs, 1 witnesses")]
+fn test_set_mode_after_alloc() {
+    // Mode::Runで割り当てたwireには場所が記録されていない
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Run);
+    cs.alloc(Fr::from(3));
+    cs.set_mode(Mode::Compile);
+}
+
+#[test]
+fn test_namespace() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(3));
+    let b = cs.namespace("gadget/inner", |cs| {
+        let b = cs.wire(a * a);
+        cs.namespace("check", |cs| cs.anchor(b - 10u32));
+        b
+    });
+    cs.anchor(b - a * 3u32);
+
+    assert_eq!(cs.wire_path(Idx::Witness(0)).as_deref(), Some(""));
+    assert_eq!(cs.wire_path(b.idx().unwrap()).as_deref(), Some("gadget/inner"));
+    assert_eq!(cs.path(0).as_deref(), Some("gadget/inner"));
+    assert_eq!(cs.path(1).as_deref(), Some("gadget/inner/check"));
+    assert_eq!(cs.path(2).as_deref(), Some(""));
+
+    let Err(CheckError::Unsatisfied(err)) = cs.check() else {
+        panic!("unsatisfied constraint expected");
+    };
+    assert_eq!(err.path, "gadget/inner/check");
+    assert!(err.to_string().starts_with("gadget/inner/check: constraint 1 at "));
+    assert!(cs.dump().contains("gadget/inner: w1 = 9"));
+
+    // Mode::Runでは何も記録しない
+    let run = ConstraintSystem::<Fr>::new_ref(Mode::Run);
+    let x = run.namespace("gadget", |cs| cs.wire(cs.one() * 2u32));
+    assert_eq!(x.raw(), Fr::from(2));
+    assert_eq!(run.wire_path(Idx::Witness(0)), None);
+
+    // 名前も文字列にしない
+    struct Unformatted;
+    impl std::fmt::Display for Unformatted {
+        fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            panic!("namespace name formatted in Mode::Run")
+        }
+    }
+    run.namespace(Unformatted, |cs| cs.wire(cs.one() * 3u32));
+}
+
+#[test]
+fn test_namespace_poseidon() {
+    use crate::test_utils::test_hash;
+
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    test_hash(&cs, &[1]);
+
+    let paths: Vec<_> = (0..cs.shape().num_constraints)
+        .map(|i| cs.path(i).unwrap())
+        .collect();
+    assert!(paths.iter().all(|p| p.starts_with("poseidon/round_")));
+    assert!(paths.iter().any(|p| p == "poseidon/round_3/sbox/pow"));
+    assert!(paths.iter().any(|p| p == "poseidon/round_67/sbox/pow"));
+}
+
+#[test]
+fn test_const() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let (x, y) = (Fr::from(3), Fr::from(5));
+    let a = cs.alloc(x);
+    let b = cs.alloc(y);
+    let c = Const(Fr::from(7));
+
+    // 定数倍はVのままなので、さらに掛けられる
+    let v: V<Fr> = a * c;
+    assert_eq!(v.raw(), x * c.0);
+    let v: V<Fr> = &a * &c + c * &b - &c;
+    assert_eq!(v.raw(), x * c.0 + c.0 * y - c.0);
+    let vv: VV<Fr> = (c * a) * (b * c) + c;
+    assert_eq!(vv.raw(), c.0 * x * y * c.0 + c.0);
+    let w = cs.wire(vv);
+    assert_eq!(w.raw(), c.0 * x * y * c.0 + c.0);
+
+    let v: V<Fr> = a.into();
+    let v = &v * c - c * v + Const::from(Fr::from(2));
+    assert_eq!(v.raw(), Fr::from(2));
+
+    // 定数同士は値だけ
+    let k: Const<Fr> = c * c - c + Const(Fr::from(1));
+    assert_eq!(k, Const(Fr::from(43)));
+
+    assert_eq!(cs.compile().num_constraints(), 1);
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_constant_folding() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a = cs.alloc(Fr::from(3));
+    let b = cs.alloc(Fr::from(4));
+    let ab = a + b;
+
+    assert_eq!(V::<Fr>::zero().as_constant(), Some(Fr::from(0)));
+    assert_eq!(V::constant(Fr::from(7)).as_constant(), Some(Fr::from(7)));
+    assert_eq!((a * 0u32).as_constant(), Some(Fr::from(0)));
+    assert_eq!((&ab * 0u32).as_constant(), Some(Fr::from(0)));
+    assert_eq!((a - a).as_constant(), Some(Fr::from(0)));
+    assert_eq!((&ab - &ab).as_constant(), Some(Fr::from(0)));
+    assert_eq!((V::constant(Fr::from(2)) * 3u32 + 1u32).as_constant(), Some(Fr::from(7)));
+    assert_eq!((a + b - a).as_constant(), None);
+
+    // x + 0, x - 0, x * 1 はノードを作らない
+    let structure = |f: fn(Wire<Fr>) -> V<Fr>| {
+        let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+        cs.wire(f(cs.alloc(Fr::from(3))));
+        cs.structure()
+    };
+    let expected = structure(V::from);
+    assert_ne!(structure(|a| a + 1u32), expected);
+    assert_eq!(structure(|a| a + 0u32), expected);
+    assert_eq!(structure(|a| a - 0u32), expected);
+    assert_eq!(structure(|a| a * 1u32), expected);
+    assert_eq!(structure(|a| V::zero() + a), expected);
+    assert_eq!(structure(|a| a * Const(Fr::from(1))), expected);
+
+    // プレースホルダーの0は制約に残らない
+    let sum = [a, b].iter().fold(V::zero(), |acc, x| acc + x);
+    assert_eq!(sum.lc(), ab.lc());
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_readme_expressions() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let a: Wire<Fr> = cs.alloc(Fr::from(11));
+    let b: Wire<Fr> = cs.alloc(Fr::from(22));
+
+    let c: V<Fr> = a + b + 1;
+    assert_eq!(c.raw(), Fr::from(34));
+    let d: V<Fr> = 2 * a + 3 * b + 4;
+    assert_eq!(d.raw(), Fr::from(92));
+    let e: VV<Fr> = a * b;
+    assert_eq!(e.raw(), Fr::from(242));
+    let w = cs.wire(a * b - 1u64);
+    assert_eq!(w.raw(), Fr::from(241));
+}
+
+#[test]
+fn test_primitive_lhs() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let mut rng = test_rng();
+    let x = Fr::rand(&mut rng);
+    let y = Fr::rand(&mut rng);
+
+    let a = cs.alloc(x);
+    let b = cs.alloc(y);
+    let v = a + b;
+    let vv = a * b;
+
+    assert_eq!((true + a).raw(), x + Fr::from(1));
+    assert_eq!((false * &a).raw(), Fr::from(0));
+    assert_eq!((3u8 * a).raw(), Fr::from(3) * x);
+    assert_eq!((3u16 - &a).raw(), Fr::from(3) - x);
+    assert_eq!((3u32 + v.clone()).raw(), Fr::from(3) + x + y);
+    assert_eq!((3u64 * &v).raw(), Fr::from(3) * (x + y));
+    assert_eq!((3u128 - vv.clone()).raw(), Fr::from(3) - x * y);
+    assert_eq!((-3i8 * &vv).raw(), -Fr::from(3) * x * y);
+    assert_eq!((-3i16 + a).raw(), x - Fr::from(3));
+    assert_eq!((-3i32 * &v).raw(), -Fr::from(3) * (x + y));
+    assert_eq!((-3i64 - vv.clone()).raw(), -Fr::from(3) - x * y);
+    assert_eq!((-3i128 * a).raw(), -Fr::from(3) * x);
+    assert_eq!((a - 3i32).raw(), x - Fr::from(3));
+    assert_eq!((vv.clone() * -1i32).raw(), -(x * y));
+    // 体の元はConstで包む
+    assert_eq!((Const(y) * a).raw(), y * x);
+
+    let w = cs.wire(2 * (-1 * a + 3) * (&v * 4) - 6);
+    assert_eq!(w.raw(), Fr::from(8) * (Fr::from(3) - x) * (x + y) - Fr::from(6));
+    let u = cs.wire(5 * vv - 1);
+    assert_eq!(u.raw(), Fr::from(5) * x * y - Fr::from(1));
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_inverse_and_div() {
+    let cs = ConstraintSystemRef::<Fr>::new(Mode::Compile);
+    let (a, b) = (cs.alloc(6), cs.alloc(4));
+    let inv = cs.inverse(&a);
+    assert_eq!(inv.raw() * Fr::from(6), Fr::from(1));
+    let q = cs.wire(a / b);
+    assert_eq!(q.raw() * Fr::from(4), Fr::from(6));
+    let r = cs.wire((a + b * 2u32) / &b);
+    assert_eq!(r.raw() * Fr::from(4), Fr::from(14));
+    let s = cs.wire(Const(Fr::from(3)) / (q - 1u32));
+    assert_eq!(s.raw(), Fr::from(6));
+    // 割り算はどれも1つの制約
+    assert_eq!(cs.shape().num_constraints, 4);
+    assert_eq!(cs.division_by_zero(), None);
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_division_by_zero() {
+    for mode in [Mode::Compile, Mode::Run] {
+        let cs = ConstraintSystemRef::<Fr>::new(mode);
+        let (a, zero) = (cs.alloc(6), cs.alloc(0));
+        let line = line!() + 1;
+        let q = cs.wire(a / zero);
+        cs.inverse(&zero);
+        // 商はゼロになり、最初の場所が報告される
+        assert_eq!(q.raw(), Fr::from(0));
+        let err = cs.division_by_zero().unwrap();
+        assert_eq!((err.site.file(), err.site.line()), (file!(), line));
+        assert!(err.to_string().starts_with("division by zero at "));
+        if let Mode::Compile = mode {
+            let Err(CheckError::Unsatisfied(err)) = cs.check() else {
+                panic!("unsatisfied constraint expected");
+            };
+            assert_eq!(err.index, 0);
+        }
+    }
+}
+
+#[test]
+fn test_div_const() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let (a, b) = (cs.alloc(6), cs.alloc(4));
+    let k = Const(Fr::from(3));
+    let inv = Fr::from(3).inverse().unwrap();
+    // 逆数倍になり、ヒントも制約も使わない
+    let v = a / k;
+    assert_eq!(v.raw(), Fr::from(2));
+    assert_eq!(v.lc(), (a * Const(inv)).lc());
+    assert_eq!((&(a + b) / &k).lc(), ((a + b) * Const(inv)).lc());
+    let vv = (a * b) / k;
+    assert_eq!(vv.raw(), Fr::from(8));
+    assert_eq!(vv.flatten(), (a * b * Const(inv)).flatten());
+    assert_eq!(cs.shape().num_constraints, 0);
+
+    let w = cs.wire(vv - v);
+    assert_eq!(w.raw(), Fr::from(6));
+    assert_eq!(cs.shape().num_constraints, 1);
+    assert_eq!(cs.division_by_zero(), None);
+    assert_eq!(cs.check(), Ok(()));
+}
+
+#[test]
+fn test_div_const_zero() {
+    for mode in [Mode::Compile, Mode::Run] {
+        let cs = ConstraintSystem::<Fr>::new_ref(mode);
+        let a = cs.alloc(6);
+        let line = line!() + 1;
+        let v = -(a / Const(Fr::from(0))) + 1u32;
+        let vv = (a * a) / &Const(Fr::from(0));
+        // panicせず値はゼロになり、wireかanchorに渡ったときに報告される
+        assert_eq!((v.raw(), vv.raw()), (Fr::from(1), Fr::from(0)));
+        assert_eq!(cs.division_by_zero(), None);
+        cs.anchor(vv);
+        cs.wire(v);
+        let err = cs.division_by_zero().unwrap();
+        assert_eq!((err.site.file(), err.site.line()), (file!(), line + 1));
+    }
+
+    let circuit = |cs: ConstraintSystemRef<Fr>, k: u64| {
+        let a = cs.alloc(6);
+        cs.wire(a / Const(Fr::from(k)));
+    };
+    let compiled = crate::CompiledCircuit::compile(|cs| circuit(cs, 2));
+    assert!(compiled.run(|cs| circuit(cs, 3)).is_ok());
+    assert!(matches!(
+        compiled.run(|cs| circuit(cs, 0)),
+        Err(crate::RunError::DivisionByZero(_))
+    ));
+}
+
+#[test]
+fn test_zero_by_zero() {
+    let cs = ConstraintSystemRef::<Fr>::new(Mode::Compile);
+    let zero = cs.alloc(0);
+    let q = cs.wire(zero / zero);
+    // 報告はされるが、q * 0 = 0 はどんな商でも満たされる
+    assert!(cs.division_by_zero().is_some());
+    assert_eq!(cs.check(), Ok(()));
+    let (x, mut w) = cs.witnesses();
+    assert_eq!(q.raw(), w[1]);
+    w[1] = Fr::from(42);
+    assert_eq!(cs.compile().check(&[x, w].concat()), Ok(()));
+
+    // 分母を非ゼロに制約すれば満たせない
+    enforce_not_equal(cs.clone(), zero, V::zero());
+    assert!(cs.check().is_err());
+}
+
+#[test]
+#[should_panic(expected = "not rank-1")]
+fn test_wire_sum_of_products() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let (a, b) = (cs.alloc(3), cs.alloc(4));
+    // compileを待たず、wireの時点で拒否される
+    cs.wire(a * b + b * b);
+}
+
+#[test]
+#[should_panic(expected = "not rank-1")]
+fn test_anchor_sum_of_products() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let (a, b) = (cs.alloc(3), cs.alloc(4));
+    cs.anchor(-(a * b * 2u32) + 1u32 - (a * a));
+}
+
+#[test]
+fn test_rank_1_products() {
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Compile);
+    let (a, b) = (cs.alloc(3), cs.alloc(4));
+    let zero = V::zero() * b;
+    // 定数に畳み込まれる積と定数倍は数えない
+    cs.wire(a * b + zero.clone() * Const(Fr::from(2)) - a);
+    cs.wire(-(a * b) * 3u32 + (a - a) * b);
+    cs.anchor(Const(Fr::from(2)) * (a * b) - 24u32);
+    // 線形結合として定数になる因子も、flattenと同じく積に数えない
+    let vv = ((a + 1u32) - a) * b + a * b;
+    assert!(vv.flatten().is_some());
+    let w = cs.wire(vv);
+    assert_eq!(w.raw(), Fr::from(16));
+    assert_eq!(cs.check(), Ok(()));
+
+    // Mode::Runでは数えない
+    let cs = ConstraintSystem::<Fr>::new_ref(Mode::Run);
+    let (a, b) = (cs.alloc(3), cs.alloc(4));
+    cs.wire(a * b + V::zero() * b);
+}
@@ -3,15 +3,23 @@ use ark_ff::Field;
 use crate::{variables::V, CSRef};
 
 
+/// `base^exp` by square-and-multiply. The first multiplication and the last
+/// squaring are skipped, so e.g. `x^5` costs 3 constraints.
 pub fn pow<F: Field>(cs: CSRef<F>, mut base: V<F>, mut exp: u64) -> V<F> {
-    let mut pow = cs.one();
-    while exp > 0 {
-        if exp % 2 == 1 {
-            pow = cs.wire(pow * &base);
+    cs.namespace("pow", |cs| {
+        let mut pow: Option<V<F>> = None;
+        while exp > 0 {
+            if exp % 2 == 1 {
+                pow = Some(match pow {
+                    None => base.clone(),
+                    Some(pow) => cs.wire(pow * &base).into(),
+                });
+            }
+            exp /= 2;
+            if exp > 0 {
+                base = cs.wire(&base * &base).into();
+            }
         }
-        base = cs.wire(&base * &base).into();
-        exp /= 2;
-    }
-
-    pow.into()
+        pow.unwrap_or_else(|| cs.one().into())
+    })
 }
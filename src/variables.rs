@@ -1,14 +1,50 @@
 use std::{
     cell::RefCell,
+    collections::{HashMap, hash_map::DefaultHasher},
+    fmt::{self, Write},
+    hash::{Hash, Hasher},
     iter::Sum,
-    ops::{Add, AddAssign, Mul, Sub},
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub},
+    panic::Location,
     rc::Rc,
 };
 
 use ark_ff::Field;
 
-#[derive(Clone, Copy, Debug)]
-pub struct Idx(usize);
+use crate::{
+    circuit::Shape,
+    lc::{Flat, Flattener, LinearCombination, flatten, is_rank_1},
+    profile::{Cost, Profile},
+    r1cs::{CheckError, R1CS, Unsatisfied},
+};
+
+/// Index of an allocated value. Inputs and witnesses have separate index spaces
+/// and `Input(0)` is the constant one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Idx {
+    Input(usize),
+    Witness(usize),
+}
+
+impl fmt::Display for Idx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Idx::Input(0) => write!(f, "1"),
+            Idx::Input(i) => write!(f, "x{i}"),
+            Idx::Witness(i) => write!(f, "w{i}"),
+        }
+    }
+}
+
+impl Idx {
+    /// Column in `z = (1, x, w)`, where `num_instance` is `|(1, x)|`.
+    pub fn column(self, num_instance: usize) -> usize {
+        match self {
+            Idx::Input(i) => i,
+            Idx::Witness(i) => num_instance + i,
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Wire<F: Field> {
@@ -16,13 +52,163 @@ pub struct Wire<F: Field> {
     val: F,
 }
 
+/// Handle to an expression DAG. Leaves are stored inline and operator nodes are
+/// shared through `Rc`, so cloning a `V`/`VV` or building a new operator on top of
+/// it is O(1) regardless of how deep the expression already is.
 #[derive(Clone, Debug)]
-pub enum Exp<F> {
+pub enum Exp<F: Field> {
     Idx(Idx),
-    Coe(F),                        // 係数
-    Add(Box<Exp<F>>, Box<Exp<F>>), // 加算
-    Sub(Box<Exp<F>>, Box<Exp<F>>), // 減算
-    Mul(Box<Exp<F>>, Box<Exp<F>>), // 乗算
+    Coe(F), // 係数
+    Node(Rc<Node<F>>),
+}
+
+#[derive(Debug)]
+pub enum Node<F: Field> {
+    Add(Exp<F>, Exp<F>), // 加算
+    Sub(Exp<F>, Exp<F>), // 減算
+    Mul(Exp<F>, Exp<F>), // 乗算
+}
+
+impl<F: Field> Node<F> {
+    pub fn operands(&self) -> (&Exp<F>, &Exp<F>) {
+        match self {
+            Node::Add(x, y) | Node::Sub(x, y) | Node::Mul(x, y) => (x, y),
+        }
+    }
+
+    fn take_operands(&mut self, stack: &mut Vec<Rc<Node<F>>>) {
+        let (Node::Add(x, y) | Node::Sub(x, y) | Node::Mul(x, y)) = self;
+        for exp in [x, y] {
+            if let Exp::Node(node) = std::mem::replace(exp, Exp::Coe(F::ZERO)) {
+                stack.push(node);
+            }
+        }
+    }
+}
+
+// 深いDAGを再帰的にdropするとスタックが溢れるので、所有権を持つノードを明示的なスタックで解放する。
+impl<F: Field> Drop for Node<F> {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        self.take_operands(&mut stack);
+        while let Some(node) = stack.pop() {
+            if let Ok(mut node) = Rc::try_unwrap(node) {
+                node.take_operands(&mut stack);
+            }
+        }
+    }
+}
+
+// 共有ノードはmemoで一度だけハッシュする
+fn structure_hash<F: Field>(exp: &Exp<F>, memo: &mut HashMap<*const Node<F>, u64>) -> u64 {
+    fn hash<T: Hash>(value: T) -> u64 {
+        let mut h = DefaultHasher::new();
+        value.hash(&mut h);
+        h.finish()
+    }
+    let leaf = |exp: &Exp<F>, memo: &HashMap<*const Node<F>, u64>| match exp {
+        Exp::Idx(idx) => hash((0u8, idx)),
+        Exp::Coe(c) => hash((1u8, c)),
+        Exp::Node(node) => memo[&Rc::as_ptr(node)],
+    };
+    let Exp::Node(root) = exp else {
+        return leaf(exp, memo);
+    };
+    let mut stack = vec![(root, false)];
+    while let Some((node, expanded)) = stack.pop() {
+        if memo.contains_key(&Rc::as_ptr(node)) {
+            continue;
+        }
+        let (x, y) = node.operands();
+        if !expanded {
+            stack.push((node, true));
+            for child in [y, x] {
+                if let Exp::Node(child) = child {
+                    stack.push((child, false));
+                }
+            }
+            continue;
+        }
+        let tag: u8 = match node.as_ref() {
+            Node::Add(..) => 2,
+            Node::Sub(..) => 3,
+            Node::Mul(..) => 4,
+        };
+        let h = hash((tag, leaf(x, memo), leaf(y, memo)));
+        memo.insert(Rc::as_ptr(node), h);
+    }
+    leaf(exp, memo)
+}
+
+impl<F: Field> From<Node<F>> for Exp<F> {
+    fn from(node: Node<F>) -> Self {
+        Exp::Node(Rc::new(node))
+    }
+}
+
+impl<F: Field> Exp<F> {
+    fn as_constant(&self) -> Option<F> {
+        match self {
+            Exp::Coe(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    // 同じwireか、同じノードを共有している
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Exp::Idx(x), Exp::Idx(y)) => x == y,
+            (Exp::Node(x), Exp::Node(y)) => Rc::ptr_eq(x, y),
+            _ => false,
+        }
+    }
+}
+
+// 演算は定数を畳み込み、0や1との演算ではノードを作らない
+
+impl<F: Field> Add for Exp<F> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        match (self.as_constant(), rhs.as_constant()) {
+            (Some(x), Some(y)) => Exp::Coe(x + y),
+            (Some(x), _) if x.is_zero() => rhs,
+            (_, Some(y)) if y.is_zero() => self,
+            _ => Node::Add(self, rhs).into(),
+        }
+    }
+}
+
+impl<F: Field> Sub for Exp<F> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        match (self.as_constant(), rhs.as_constant()) {
+            (Some(x), Some(y)) => Exp::Coe(x - y),
+            (_, Some(y)) if y.is_zero() => self,
+            _ if self.same(&rhs) => Exp::Coe(F::ZERO),
+            _ => Node::Sub(self, rhs).into(),
+        }
+    }
+}
+
+impl<F: Field> Mul for Exp<F> {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        match (self.as_constant(), rhs.as_constant()) {
+            (Some(x), Some(y)) => Exp::Coe(x * y),
+            (Some(x), _) | (_, Some(x)) if x.is_zero() => Exp::Coe(F::ZERO),
+            (Some(x), _) if x.is_one() => rhs,
+            (_, Some(y)) if y.is_one() => self,
+            _ => Node::Mul(self, rhs).into(),
+        }
+    }
+}
+
+// 符号反転は係数 -1 との積として記録する
+impl<F: Field> Neg for Exp<F> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        self * Exp::Coe(-F::ONE)
+    }
 }
 
 impl<F: Field> From<Idx> for Exp<F> {
@@ -35,106 +221,477 @@ impl<F: Field> From<Idx> for Exp<F> {
 pub struct V<F: Field> {
     val: F,
     exp: Option<Exp<F>>,
+    // Const(0)で割った場所。csを持たないので、wireかanchorで報告する
+    division_by_zero: Option<&'static Location<'static>>,
 }
 #[derive(Clone)]
 pub struct VV<F: Field> {
     val: F,
     exp: Option<Exp<F>>,
+    // 含まれる積の数 (2で飽和)。wireとanchorで2つ以上を拒否する
+    products: u8,
+    division_by_zero: Option<&'static Location<'static>>,
 }
+/// A field constant known while building the circuit.
+///
+/// Multiplying a `Wire` or `V` by a `Const` keeps it linear, so it costs no wire and
+/// can still be multiplied again. Dividing by a `Const` multiplies by its inverse.
+/// Operations between constants are evaluated directly and record nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Const<F: Field>(pub F);
 
 impl<F: Field> Wire<F> {
     pub fn raw(&self) -> F {
         self.val
     }
+    pub fn idx(&self) -> Option<Idx> {
+        self.exp
+    }
 }
 impl<F: Field> V<F> {
+    pub fn zero() -> Self {
+        Self::constant(F::ZERO)
+    }
+    pub fn constant(c: F) -> Self {
+        V {
+            val: c,
+            exp: Some(Exp::Coe(c)),
+            division_by_zero: None,
+        }
+    }
     pub fn raw(&self) -> F {
         self.val
     }
+    /// The value if this is known to be constant while building the circuit.
+    pub fn as_constant(&self) -> Option<F> {
+        self.exp.as_ref().and_then(Exp::as_constant)
+    }
+    /// Canonical linear combination of this expression. `None` in `Mode::Run`.
+    pub fn lc(&self) -> Option<LinearCombination<F>> {
+        self.exp.as_ref().map(|exp| match flatten(exp) {
+            Flat::Linear(lc) => lc,
+            Flat::Quadratic { .. } => unreachable!("V is always linear"),
+        })
+    }
+}
+
+/// `num / den`, produced by `/` on `Wire` and `V`. Becomes a wire with
+/// [`ConstraintSystemRef::wire`], which allocates the quotient as a hint and anchors
+/// `quotient * den - num`, a single constraint.
+///
+/// The constraint only pins the quotient down while `den` is non-zero. If both
+/// `num` and `den` are zero, it holds for any quotient, so a prover can assign
+/// anything. Anchor `den` as non-zero separately, for example with
+/// [`enforce_not_equal`](crate::gadgets::cmp::enforce_not_equal), if `0 / 0` can occur.
+#[derive(Clone, Debug)]
+pub struct Quotient<F: Field> {
+    num: V<F>,
+    den: V<F>,
+}
+
+impl<F: Field> Const<F> {
+    pub fn raw(&self) -> F {
+        self.0
+    }
 }
 
 impl<F: Field> VV<F> {
     pub fn raw(&self) -> F {
         self.val
     }
+    /// Rank-1 form of this expression. `None` in `Mode::Run`.
+    pub fn flatten(&self) -> Option<Flat<F>> {
+        self.exp.as_ref().map(flatten)
+    }
+    // compileまで待たず、wireやanchorの呼び出し元で拒否する。
+    // 積の数は上限なので、2つ以上のときだけcompileと同じFlattenerで確かめる。
+    // Mode::Runでは式がないので確かめない
+    #[track_caller]
+    fn assert_rank_1(&self) {
+        assert!(
+            Operand::<F>::products(self) <= 1 || self.exp.as_ref().is_none_or(is_rank_1),
+            "expression is not rank-1: sum of several products, wire all but one of them first"
+        );
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum Mode {
+    /// Records expressions and constraints.
     Compile,
+    /// Only computes values. No expression is built.
     Run,
 }
 
-pub struct R1CS;
+/// A value was divided by zero while tracing. The quotient is assigned zero, so the
+/// trace does not satisfy its constraints, unless the numerator was zero as well:
+/// `0 / 0` satisfies them with any quotient (see [`Quotient`]).
+///
+/// Dividing by `Const(0)` records no constraint at all. The result is zero and the
+/// site of the `/` is reported once the result reaches `wire` or `anchor`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DivisionByZero {
+    pub site: &'static Location<'static>,
+}
+
+impl fmt::Display for DivisionByZero {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "division by zero at {}", self.site)
+    }
+}
+
+impl std::error::Error for DivisionByZero {}
+
+// 制約やwireを記録した場所と、そのときのnamespace
+#[derive(Clone, Copy, Debug)]
+struct Origin {
+    site: &'static Location<'static>,
+    scope: usize,
+}
 
 #[derive(Clone, Debug)]
 pub struct ConstraintSystem<F: Field> {
+    inputs: Vec<F>,
     wires: Vec<F>,
     exprs: Vec<(Option<Idx>, Exp<F>)>,
+    origins: Vec<Origin>,
+    input_origins: Vec<Origin>,
+    wire_origins: Vec<Origin>,
+    // namespaceのパス。0番はルートの空文字列
+    scopes: Vec<String>,
+    scope_ids: HashMap<String, usize>,
+    scope: usize,
+    num_constraints: usize,
+    // 最初のゼロ除算
+    division_by_zero: Option<DivisionByZero>,
     mode: Mode,
 }
 
 impl<F: Field> ConstraintSystem<F> {
     pub fn new(mode: Mode) -> Self {
         Self {
-            wires: vec![F::ONE],
+            inputs: vec![F::ONE],
+            wires: vec![],
             exprs: vec![],
+            origins: vec![],
+            input_origins: vec![],
+            wire_origins: vec![],
+            scopes: vec![String::new()],
+            scope_ids: HashMap::new(),
+            scope: 0,
+            num_constraints: 0,
+            division_by_zero: None,
             mode,
         }
     }
     pub fn new_ref(mode: Mode) -> ConstraintSystemRef<F> {
         ConstraintSystemRef::<F>::new(mode)
     }
+    /// Panics once anything has been allocated or constrained. Expressions and
+    /// source locations are only recorded in `Mode::Compile`, so a trace that
+    /// switched modes midway would look them up at the wrong indices.
+    #[track_caller]
     pub fn set_mode(&mut self, mode: Mode) {
+        let Shape {
+            num_inputs,
+            num_witnesses,
+            num_constraints,
+        } = self.shape();
+        assert!(
+            num_inputs + num_witnesses + num_constraints == 0,
+            "set_mode after the trace has started: {num_inputs} inputs, \
+             {num_witnesses} witnesses and {num_constraints} constraints recorded"
+        );
         self.mode = mode
     }
-    pub fn compile(&self) -> R1CS {
+    pub fn compile(&self) -> R1CS<F> {
         match self.mode {
-            Mode::Compile => todo!(),
-            Mode::Run => todo!(),
+            Mode::Compile => {
+                R1CS::from_exprs(&self.exprs, self.inputs.len() - 1, self.wires.len())
+            }
+            Mode::Run => panic!("Constraints are not recorded in Mode::Run"),
         }
     }
-    pub fn witnesses(&self) -> Vec<F> {
-        self.wires.clone()
+    /// Returns `(instance, witness)`. `instance[0]` is the constant one, so the full
+    /// assignment is `z = instance ++ witness`.
+    pub fn witnesses(&self) -> (Vec<F>, Vec<F>) {
+        (self.inputs.clone(), self.wires.clone())
+    }
+    /// Nothing is recorded in `Mode::Run`, so a run cannot be checked and this
+    /// returns [`CheckError::NotRecorded`].
+    pub fn check(&self) -> Result<(), CheckError<F>> {
+        if let Mode::Run = self.mode {
+            return Err(CheckError::NotRecorded);
+        }
+        let z = [&self.inputs[..], &self.wires[..]].concat();
+        self.compile().check_rows(&z).map_err(|e| {
+            CheckError::Unsatisfied(Unsatisfied {
+                site: self.site(e.index),
+                path: self.path(e.index).unwrap_or_default().to_string(),
+                ..e
+            })
+        })
+    }
+    /// Counted in both modes, so a `Mode::Run` trace can be compared with a compiled one.
+    pub fn shape(&self) -> Shape {
+        Shape {
+            num_inputs: self.inputs.len() - 1,
+            num_witnesses: self.wires.len(),
+            num_constraints: self.num_constraints,
+        }
     }
+    /// Hash of each recorded constraint's structure: the wires it refers to, its
+    /// constants and operators, but not the witness values.
+    pub fn structure(&self) -> Vec<u64> {
+        let mut memo = HashMap::new();
+        self.exprs
+            .iter()
+            .map(|(wire, exp)| {
+                let mut h = DefaultHasher::new();
+                (wire, structure_hash(exp, &mut memo)).hash(&mut h);
+                h.finish()
+            })
+            .collect()
+    }
+    /// Where the `i`-th constraint was recorded.
+    pub fn site(&self, i: usize) -> Option<&'static Location<'static>> {
+        self.origins.get(i).map(|o| o.site)
+    }
+    /// Where the wire was allocated.
+    pub fn wire_site(&self, idx: Idx) -> Option<&'static Location<'static>> {
+        self.wire_origin(idx).map(|o| o.site)
+    }
+    /// Namespace the `i`-th constraint was recorded in. Empty at the top level.
+    pub fn path(&self, i: usize) -> Option<&str> {
+        self.origins.get(i).map(|o| self.scopes[o.scope].as_str())
+    }
+    /// Namespace the wire was allocated in. Empty at the top level.
+    pub fn wire_path(&self, idx: Idx) -> Option<&str> {
+        self.wire_origin(idx).map(|o| self.scopes[o.scope].as_str())
+    }
+    fn wire_origin(&self, idx: Idx) -> Option<Origin> {
+        match idx {
+            Idx::Input(0) => None,
+            Idx::Input(i) => self.input_origins.get(i - 1).copied(),
+            Idx::Witness(i) => self.wire_origins.get(i).copied(),
+        }
+    }
+    /// Enters the namespace `name` below the current one and returns the scope to
+    /// restore with [`ConstraintSystem::exit`]. Nothing is recorded in `Mode::Run`,
+    /// where `name` is not even formatted.
+    pub fn enter(&mut self, name: impl fmt::Display) -> usize {
+        let parent = self.scope;
+        if let Mode::Compile = self.mode {
+            let path = match parent {
+                0 => name.to_string(),
+                _ => format!("{}/{name}", self.scopes[parent]),
+            };
+            self.scope = match self.scope_ids.get(&path) {
+                Some(id) => *id,
+                None => {
+                    self.scopes.push(path.clone());
+                    self.scope_ids.insert(path, self.scopes.len() - 1);
+                    self.scopes.len() - 1
+                }
+            };
+        }
+        parent
+    }
+    pub fn exit(&mut self, parent: usize) {
+        self.scope = parent;
+    }
+    /// Cost of the compiled circuit per namespace and per call site.
+    pub fn profile(&self) -> Profile {
+        let r1cs = self.compile();
+        let mut scopes = vec![Cost::default(); self.scopes.len()];
+        let mut sites = HashMap::<_, Cost>::new();
+        let mut add = |origin: &Origin, cost: Cost| {
+            scopes[origin.scope] += cost;
+            *sites.entry(origin.site).or_default() += cost;
+        };
+        for origin in self.input_origins.iter().chain(&self.wire_origins) {
+            let wire = Cost {
+                wires: 1,
+                ..Default::default()
+            };
+            add(origin, wire);
+        }
+        for (i, origin) in self.origins.iter().enumerate() {
+            let constraint = Cost {
+                constraints: 1,
+                non_zeros: r1cs.a[i].len() + r1cs.b[i].len() + r1cs.c[i].len(),
+                ..Default::default()
+            };
+            add(origin, constraint);
+        }
+        Profile::new(self.scopes.iter().zip(scopes), sites)
+    }
+    #[track_caller]
+    fn origin(&self) -> Origin {
+        Origin {
+            site: Location::caller(),
+            scope: self.scope,
+        }
+    }
+    /// Human readable listing of every wire and constraint with its namespace and
+    /// source location.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        let site = |s: Option<&Location>| s.map_or(String::new(), |s| format!("  @ {s}"));
+        let path = |p: Option<&str>| match p {
+            Some(p) if !p.is_empty() => format!("{p}: "),
+            _ => String::new(),
+        };
+        let wires = self
+            .inputs
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, v)| (Idx::Input(i), v));
+        let wires = wires.chain(
+            self.wires
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (Idx::Witness(i), v)),
+        );
+        for (idx, val) in wires {
+            writeln!(
+                out,
+                "{}{idx} = {val}{}",
+                path(self.wire_path(idx)),
+                site(self.wire_site(idx))
+            )
+            .unwrap();
+        }
+        let mut flattener = Flattener::default();
+        for (i, (wire, exp)) in self.exprs.iter().enumerate() {
+            let (a, b, c) = flattener.flatten(exp).into_abc();
+            let w = wire.map(LinearCombination::from).unwrap_or_default();
+            writeln!(
+                out,
+                "{}#{i}: ({a}) * ({b}) = ({}){}",
+                path(self.path(i)),
+                w - c,
+                site(self.site(i))
+            )
+            .unwrap();
+        }
+        out
+    }
+    #[track_caller]
     pub fn wire<W: Wirable<F>>(&mut self, w: W) -> Wire<F> {
-        let vv = w.into_vv();
-        let mut wire = self.alloc(vv.val);
+        let (vv, den) = w.into_ratio();
+        self.report_division_by_zero(vv.division_by_zero);
+        if let Some(den) = den {
+            self.report_division_by_zero(den.division_by_zero);
+            return self.wire_quotient(vv, den);
+        }
+        vv.assert_rank_1();
+        let wire = self.alloc_witness(vv.val);
+        self.num_constraints += 1;
         match (vv.exp, self.mode) {
-            (None, Mode::Run) => wire.exp = None,
-            (Some(_), Mode::Run) => wire.exp = None,
+            (None, Mode::Run) => {}
+            (Some(_), Mode::Run) => {}
             (None, Mode::Compile) => panic!("Wire has to be set exp"),
             (Some(exp), Mode::Compile) => {
                 self.exprs.push((Some(wire.exp.unwrap()), exp));
+                self.origins.push(self.origin());
             }
         }
         wire
     }
+    // 商をヒントとして割り当て、w * den = num を課す
+    // num = den = 0 のときwは任意の値で制約を満たす
+    #[track_caller]
+    fn wire_quotient(&mut self, num: VV<F>, den: V<F>) -> Wire<F> {
+        let inv = den.val.inverse();
+        if inv.is_none() {
+            self.report_division_by_zero(Some(Location::caller()));
+        }
+        let wire = self.alloc_witness(num.val * inv.unwrap_or_default());
+        self.anchor(wire * den - num);
+        wire
+    }
+    /// `a⁻¹`, allocated as a hint and constrained by `a * inv = 1`.
+    #[track_caller]
+    pub fn inverse(&mut self, a: &Wire<F>) -> Wire<F> {
+        self.wire(V::constant(F::ONE) / a)
+    }
+    /// The first division by zero of this trace, if any.
+    pub fn division_by_zero(&self) -> Option<DivisionByZero> {
+        self.division_by_zero
+    }
+    // 最初の場所だけを残す
+    fn report_division_by_zero(&mut self, site: Option<&'static Location<'static>>) {
+        if let (Some(site), None) = (site, self.division_by_zero) {
+            self.division_by_zero = Some(DivisionByZero { site });
+        }
+    }
+    #[track_caller]
     pub fn alloc<T>(&mut self, val: T) -> Wire<F>
+    where
+        F: From<T>,
+    {
+        self.alloc_witness(val)
+    }
+    #[track_caller]
+    pub fn alloc_input<T>(&mut self, val: T) -> Wire<F>
+    where
+        F: From<T>,
+    {
+        let val = F::from(val);
+        let idx = Idx::Input(self.inputs.len());
+        self.inputs.push(val);
+        if let Mode::Compile = self.mode {
+            self.input_origins.push(self.origin());
+        }
+        Wire {
+            exp: self.track(idx),
+            val,
+        }
+    }
+    #[track_caller]
+    pub fn alloc_witness<T>(&mut self, val: T) -> Wire<F>
     where
         F: From<T>,
     {
         let val = F::from(val);
+        let idx = Idx::Witness(self.wires.len());
         self.wires.push(val);
+        if let Mode::Compile = self.mode {
+            self.wire_origins.push(self.origin());
+        }
         Wire {
-            exp: Some(Idx(self.wires.len())),
+            exp: self.track(idx),
             val,
         }
     }
     pub fn one(&self) -> Wire<F> {
         Wire {
-            val: self.wires[0],
-            exp: Some(Idx(0)),
+            val: self.inputs[0],
+            exp: self.track(Idx::Input(0)),
+        }
+    }
+    // Mode::RunではWireに式を持たせない。以降の演算はすべて値の計算だけになる。
+    fn track(&self, idx: Idx) -> Option<Idx> {
+        match self.mode {
+            Mode::Compile => Some(idx),
+            Mode::Run => None,
         }
     }
-    pub fn anchor<W: Wirable<F>>(&mut self, w: W) {
-        let vv = w.into_vv();
+    #[track_caller]
+    pub fn anchor<W: Into<VV<F>>>(&mut self, w: W) {
+        let vv = w.into();
+        vv.assert_rank_1();
+        self.report_division_by_zero(vv.division_by_zero);
+        self.num_constraints += 1;
         match (vv.exp, self.mode) {
             (None, Mode::Run) => {}
             (Some(_), Mode::Run) => {}
             (None, Mode::Compile) => panic!("Wire has to be set exp"),
             (Some(exp), Mode::Compile) => {
                 self.exprs.push((None, exp));
+                self.origins.push(self.origin());
             }
         }
     }
@@ -146,15 +703,66 @@ impl<F: Field> ConstraintSystemRef<F> {
     pub fn new(mode: Mode) -> Self {
         Self(Rc::new(RefCell::new(ConstraintSystem::new(mode))))
     }
+    #[track_caller]
     pub fn set_mode(&self, mode: Mode) {
-        self.0.borrow_mut().mode = mode
+        self.0.borrow_mut().set_mode(mode)
     }
-    pub fn compile(&self) -> R1CS {
+    pub fn compile(&self) -> R1CS<F> {
         self.0.borrow().compile()
     }
-    pub fn witnesses(&self) -> Vec<F> {
+    pub fn witnesses(&self) -> (Vec<F>, Vec<F>) {
         self.0.borrow().witnesses()
     }
+    /// Checks the recorded constraints against the current witnesses. Fails with
+    /// [`CheckError::NotRecorded`] in `Mode::Run`, where no constraint is recorded.
+    pub fn check(&self) -> Result<(), CheckError<F>> {
+        self.0.borrow().check()
+    }
+    pub fn shape(&self) -> Shape {
+        self.0.borrow().shape()
+    }
+    /// Structural hash of every recorded constraint. Traces of the same circuit on
+    /// different inputs must produce the same hashes.
+    pub fn structure(&self) -> Vec<u64> {
+        self.0.borrow().structure()
+    }
+    pub fn site(&self, i: usize) -> Option<&'static Location<'static>> {
+        self.0.borrow().site(i)
+    }
+    pub fn wire_site(&self, idx: Idx) -> Option<&'static Location<'static>> {
+        self.0.borrow().wire_site(idx)
+    }
+    pub fn path(&self, i: usize) -> Option<String> {
+        self.0.borrow().path(i).map(str::to_string)
+    }
+    pub fn wire_path(&self, idx: Idx) -> Option<String> {
+        self.0.borrow().wire_path(idx).map(str::to_string)
+    }
+    pub fn dump(&self) -> String {
+        self.0.borrow().dump()
+    }
+    /// Wires, constraints and non-zero matrix entries per namespace and call site.
+    /// `Display` prints it as a sorted tree.
+    pub fn profile(&self) -> Profile {
+        self.0.borrow().profile()
+    }
+
+    /// Runs `f` inside the namespace `name`, nested in the current one. Wires and
+    /// constraints recorded by `f` are tagged with the `/`-separated path, which
+    /// shows up in [`ConstraintSystemRef::dump`] and in `check` failures.
+    ///
+    /// `name` is only formatted in `Mode::Compile`, so passing `format_args!` keeps
+    /// numbered namespaces free in `Mode::Run`.
+    pub fn namespace<R>(&self, name: impl fmt::Display, f: impl FnOnce(Self) -> R) -> R {
+        if let Mode::Run = self.mode() {
+            return f(self.clone());
+        }
+        let parent = self.0.borrow_mut().enter(name);
+        let out = f(self.clone());
+        self.0.borrow_mut().exit(parent);
+        out
+    }
+    #[track_caller]
     pub fn alloc<T>(&self, val: T) -> Wire<F>
     where
         F: From<T>,
@@ -162,6 +770,25 @@ impl<F: Field> ConstraintSystemRef<F> {
         self.0.borrow_mut().alloc(val)
     }
 
+    /// Allocates a public input (instance).
+    #[track_caller]
+    pub fn alloc_input<T>(&self, val: T) -> Wire<F>
+    where
+        F: From<T>,
+    {
+        self.0.borrow_mut().alloc_input(val)
+    }
+
+    /// Allocates a private witness. Same as `alloc`.
+    #[track_caller]
+    pub fn alloc_witness<T>(&self, val: T) -> Wire<F>
+    where
+        F: From<T>,
+    {
+        self.0.borrow_mut().alloc_witness(val)
+    }
+
+    #[track_caller]
     pub fn wire<W: Wirable<F>>(&self, w: W) -> Wire<F> {
         self.0.borrow_mut().wire(w)
     }
@@ -170,7 +797,23 @@ impl<F: Field> ConstraintSystemRef<F> {
         self.0.borrow().one()
     }
 
-    pub fn anchor<W: Wirable<F>>(&self, w: W) {
+    /// `a⁻¹`, allocated as a hint and constrained by `a * inv = 1`, one constraint.
+    ///
+    /// If `a` is zero the hint is zero and the trace is unsatisfiable. The site is
+    /// reported by [`division_by_zero`](Self::division_by_zero).
+    #[track_caller]
+    pub fn inverse(&self, a: &Wire<F>) -> Wire<F> {
+        self.0.borrow_mut().inverse(a)
+    }
+
+    /// The first division by zero of this trace, if any. Values are still computed
+    /// in `Mode::Run`, so this is how a run learns that its assignment is invalid.
+    pub fn division_by_zero(&self) -> Option<DivisionByZero> {
+        self.0.borrow().division_by_zero()
+    }
+
+    #[track_caller]
+    pub fn anchor<W: Into<VV<F>>>(&self, w: W) {
         self.0.borrow_mut().anchor(w)
     }
 
@@ -179,20 +822,27 @@ impl<F: Field> ConstraintSystemRef<F> {
     }
 }
 
-// cs.wireがVとVVを同時に扱えるようにする。
+// cs.wireがV、VVと商を同時に扱えるようにする。
+// (vv, None) は w = vv、(num, Some(den)) は w * den = num として制約される。
 pub trait Wirable<F: Field> {
-    fn into_vv(self) -> VV<F>;
+    fn into_ratio(self) -> (VV<F>, Option<V<F>>);
 }
 
 impl<F: Field> Wirable<F> for VV<F> {
-    fn into_vv(self) -> VV<F> {
-        self
+    fn into_ratio(self) -> (VV<F>, Option<V<F>>) {
+        (self, None)
     }
 }
 
 impl<F: Field> Wirable<F> for V<F> {
-    fn into_vv(self) -> VV<F> {
-        VV::from(self)
+    fn into_ratio(self) -> (VV<F>, Option<V<F>>) {
+        (VV::from(self), None)
+    }
+}
+
+impl<F: Field> Wirable<F> for Quotient<F> {
+    fn into_ratio(self) -> (VV<F>, Option<V<F>>) {
+        (VV::from(self.num), Some(self.den))
     }
 }
 
@@ -201,14 +851,22 @@ impl<F: Field> Wirable<F> for V<F> {
 impl<F: Field> From<Wire<F>> for V<F> {
     fn from(Wire { exp, val }: Wire<F>) -> Self {
         let exp = exp.map(Exp::Idx);
-        Self { exp, val }
+        Self {
+            exp,
+            val,
+            division_by_zero: None,
+        }
     }
 }
 
 impl<F: Field> From<&Wire<F>> for V<F> {
     fn from(Wire { exp, val }: &Wire<F>) -> Self {
         let exp = exp.map(Exp::Idx);
-        Self { exp, val: *val }
+        Self {
+            exp,
+            val: *val,
+            division_by_zero: None,
+        }
     }
 }
 
@@ -217,36 +875,164 @@ impl<F: Field> From<V<F>> for VV<F> {
         VV {
             val: v.val,
             exp: v.exp,
+            products: 0,
+            division_by_zero: v.division_by_zero,
         }
     }
 }
+impl<F: Field> From<&V<F>> for V<F> {
+    fn from(v: &V<F>) -> Self {
+        v.clone()
+    }
+}
+
 impl<F: Field> From<&V<F>> for VV<F> {
     fn from(v: &V<F>) -> Self {
         VV {
             val: v.val,
             exp: v.exp.clone(),
+            products: 0,
+            division_by_zero: v.division_by_zero,
         }
     }
 }
 
 impl<F: Field> From<F> for V<F> {
     fn from(val: F) -> Self {
-        V {
-            val,
-            exp: Some(Exp::Coe(val)),
-        }
+        V::constant(val)
     }
 }
 
 impl<F: Field> From<&F> for V<F> {
     fn from(val: &F) -> Self {
+        V::constant(*val)
+    }
+}
+
+impl<F: Field> From<Const<F>> for V<F> {
+    fn from(Const(val): Const<F>) -> Self {
+        V::from(val)
+    }
+}
+
+impl<F: Field> From<F> for Const<F> {
+    fn from(val: F) -> Self {
+        Const(val)
+    }
+}
+
+impl<F: Field> From<&F> for Const<F> {
+    fn from(val: &F) -> Self {
+        Const(*val)
+    }
+}
+
+// 演算子の実装で、オペランドを値と式に分解し、結果を組み立てる
+trait Operand<F: Field> {
+    fn into_parts(self) -> (F, Option<Exp<F>>);
+    /// Upper bound on the number of products in the expression, saturating at 2.
+    /// Only literal constants are folded, not linear combinations that cancel.
+    fn products(&self) -> u8 {
+        0
+    }
+    /// Where a division by `Const(0)` went into the expression, if anywhere.
+    fn division_by_zero(&self) -> Option<&'static Location<'static>> {
+        None
+    }
+}
+
+trait FromParts<F: Field> {
+    fn from_parts(
+        val: F,
+        exp: Option<Exp<F>>,
+        products: u8,
+        division_by_zero: Option<&'static Location<'static>>,
+    ) -> Self;
+}
+
+fn count_products(lhs: u8, rhs: u8) -> u8 {
+    lhs.saturating_add(rhs).min(2)
+}
+
+// 定数に畳み込まれた式は積を含まない
+fn is_constant<F: Field>(exp: &Option<Exp<F>>) -> bool {
+    matches!(exp, Some(Exp::Coe(_)))
+}
+
+impl<F: Field> Operand<F> for Wire<F> {
+    fn into_parts(self) -> (F, Option<Exp<F>>) {
+        (self.val, self.exp.map(Exp::Idx))
+    }
+}
+
+impl<F: Field> Operand<F> for V<F> {
+    fn into_parts(self) -> (F, Option<Exp<F>>) {
+        (self.val, self.exp)
+    }
+    fn division_by_zero(&self) -> Option<&'static Location<'static>> {
+        self.division_by_zero
+    }
+}
+
+impl<F: Field> Operand<F> for VV<F> {
+    fn into_parts(self) -> (F, Option<Exp<F>>) {
+        (self.val, self.exp)
+    }
+    fn products(&self) -> u8 {
+        match is_constant(&self.exp) {
+            true => 0,
+            false => self.products,
+        }
+    }
+    fn division_by_zero(&self) -> Option<&'static Location<'static>> {
+        self.division_by_zero
+    }
+}
+
+impl<F: Field> Operand<F> for Const<F> {
+    fn into_parts(self) -> (F, Option<Exp<F>>) {
+        (self.0, Some(Exp::Coe(self.0)))
+    }
+}
+
+impl<F: Field> FromParts<F> for V<F> {
+    fn from_parts(
+        val: F,
+        exp: Option<Exp<F>>,
+        _: u8,
+        division_by_zero: Option<&'static Location<'static>>,
+    ) -> Self {
         V {
-            val: *val,
-            exp: Some(Exp::Coe(*val)),
+            val,
+            exp,
+            division_by_zero,
         }
     }
 }
 
+impl<F: Field> FromParts<F> for VV<F> {
+    fn from_parts(
+        val: F,
+        exp: Option<Exp<F>>,
+        products: u8,
+        division_by_zero: Option<&'static Location<'static>>,
+    ) -> Self {
+        VV {
+            val,
+            exp,
+            products,
+            division_by_zero,
+        }
+    }
+}
+
+// 定数同士の演算は値だけを計算する
+impl<F: Field> FromParts<F> for Const<F> {
+    fn from_parts(val: F, _: Option<Exp<F>>, _: u8, _: Option<&'static Location<'static>>) -> Self {
+        Const(val)
+    }
+}
+
 macro_rules! impl_op {
 
     // lhs op rhs
@@ -298,7 +1084,7 @@ macro_rules! impl_op {
             type Output = $output<F>;
             fn $method(self, rhs: $rhs) -> Self::Output {
                 let val: F = rhs.into();
-                impl_op!(@inner $trait, self, V { val, exp: Some(Exp::Coe(val)) }, $method, $output)
+                impl_op!(@inner $trait, self, V::constant(val), $method, $output)
             }
         }
         impl_op!(@ref $trait, $method, $lhs, &#$rhs, $output);
@@ -312,7 +1098,7 @@ macro_rules! impl_op {
             type Output = $output<F>;
             fn $method(self, rhs: &$rhs) -> Self::Output {
                 let val: F = rhs.clone().into();
-                impl_op!(@inner $trait, self, V { val, exp: Some(Exp::Coe(val)) }, $method, $output)
+                impl_op!(@inner $trait, self, V::constant(val), $method, $output)
             }
         }
     };
@@ -323,7 +1109,7 @@ macro_rules! impl_op {
             type Output = $output<F>;
             fn $method(self, rhs: $rhs) -> Self::Output {
                 let val: F = rhs.into();
-                impl_op!(@inner $trait, self.clone(), V { val, exp: Some(Exp::Coe(val)) }, $method, $output)
+                impl_op!(@inner $trait, self.clone(), V::constant(val), $method, $output)
             }
         }
     };
@@ -334,99 +1120,118 @@ macro_rules! impl_op {
             type Output = $output<F>;
             fn $method(self, rhs: &$rhs) -> Self::Output {
                 let val: F = rhs.clone().into();
-                impl_op!(@inner $trait, self.clone(), V { val, exp: Some(Exp::Coe(val)) }, $method, $output)
+                impl_op!(@inner $trait, self.clone(), V::constant(val), $method, $output)
             }
         }
     };
 
-    // lhs op ty
+    // ty op lhs
+    ($trait:ident, $method:ident, #$lhs:ident, $rhs:ident, $output:ident) => {
+        impl_op!(@ref $trait, $method, #$lhs, $rhs, $output);
+        impl_op!(@ref $trait, $method, #$lhs, &$rhs, $output);
+    };
+
+    // ty op rhs
     (@ref $trait:ident, $method:ident, #$lhs:ident, $rhs:ident, $output:ident) => {
         impl<F: Field> $trait<$rhs<F>> for $lhs {
             type Output = $output<F>;
             fn $method(self, rhs: $rhs<F>) -> Self::Output {
                 let val: F = self.into();
-                impl_op!(@inner $trait, V { val, exp: Some(Exp::Coe(val)) }, rhs, $method, $output)
+                impl_op!(@inner $trait, V::constant(val), rhs, $method, $output)
+            }
+        }
+    };
+
+    // ty op &rhs
+    (@ref $trait:ident, $method:ident, #$lhs:ident, &$rhs:ident, $output:ident) => {
+        impl<'a, F: Field> $trait<&'a $rhs<F>> for $lhs {
+            type Output = $output<F>;
+            fn $method(self, rhs: &'a $rhs<F>) -> Self::Output {
+                let val: F = self.into();
+                impl_op!(@inner $trait, V::constant(val), rhs.clone(), $method, $output)
             }
         }
     };
 
     // 共通の内部処理
     (@inner $trait:ident, $lhs:expr, $rhs:expr, $method:ident, $output:ident) => {{
-        let lhs = $lhs;
-        let rhs = $rhs;
-        let exp = match (lhs.exp, rhs.exp) {
-            (Some(x), Some(y)) => Some(Exp::$trait(Box::new(x.into()), Box::new(y.into()))),
+        let (lhs, rhs) = ($lhs, $rhs);
+        let (lp, rp) = (Operand::<F>::products(&lhs), Operand::<F>::products(&rhs));
+        let division_by_zero =
+            Operand::<F>::division_by_zero(&lhs).or(Operand::<F>::division_by_zero(&rhs));
+        let (lval, lexp) = Operand::<F>::into_parts(lhs);
+        let (rval, rexp) = Operand::<F>::into_parts(rhs);
+        let products = impl_op!(@products $trait, lp, rp, &lexp, &rexp);
+        let exp = match (lexp, rexp) {
+            (Some(x), Some(y)) => Some(x.$method(y)),
             _ => None,
         };
-        $output {
-            val: lhs.val.$method(rhs.val),
-            exp,
-        }
+        $output::from_parts(lval.$method(rval), exp, products, division_by_zero)
     }};
+
+    // 定数倍は積の数を変えず、線形同士の積は1つの積になる
+    (@products Mul, $lp:expr, $rp:expr, $lexp:expr, $rexp:expr) => {
+        match (is_constant($lexp), is_constant($rexp)) {
+            (true, _) => $rp,
+            (_, true) => $lp,
+            _ => count_products($lp, $rp).saturating_add(1).min(2),
+        }
+    };
+    (@products $trait:ident, $lp:expr, $rp:expr, $lexp:expr, $rexp:expr) => {
+        count_products($lp, $rp)
+    };
 }
 
-impl_op!(Add, add, Wire, #bool, V);
-impl_op!(Add, add, Wire, #u8, V);
-impl_op!(Add, add, Wire, #u16, V);
-impl_op!(Add, add, Wire, #u32, V);
-impl_op!(Add, add, Wire, #u64, V);
-impl_op!(Add, add, Wire, #u128, V);
-
-impl_op!(Add, add, V, #bool, V);
-impl_op!(Add, add, V, #u8, V);
-impl_op!(Add, add, V, #u16, V);
-impl_op!(Add, add, V, #u32, V);
-impl_op!(Add, add, V, #u64, V);
-impl_op!(Add, add, V, #u128, V);
-
-impl_op!(Add, add, VV, #bool, VV);
-impl_op!(Add, add, VV, #u8, VV);
-impl_op!(Add, add, VV, #u16, VV);
-impl_op!(Add, add, VV, #u32, VV);
-impl_op!(Add, add, VV, #u64, VV);
-impl_op!(Add, add, VV, #u128, VV);
-
-impl_op!(Sub, sub, Wire, #bool, V);
-impl_op!(Sub, sub, Wire, #u8, V);
-impl_op!(Sub, sub, Wire, #u16, V);
-impl_op!(Sub, sub, Wire, #u32, V);
-impl_op!(Sub, sub, Wire, #u64, V);
-impl_op!(Sub, sub, Wire, #u128, V);
-
-impl_op!(Sub, sub, V, #bool, V);
-impl_op!(Sub, sub, V, #u8, V);
-impl_op!(Sub, sub, V, #u16, V);
-impl_op!(Sub, sub, V, #u32, V);
-impl_op!(Sub, sub, V, #u64, V);
-impl_op!(Sub, sub, V, #u128, V);
-
-impl_op!(Sub, sub, VV, #bool, VV);
-impl_op!(Sub, sub, VV, #u8, VV);
-impl_op!(Sub, sub, VV, #u16, VV);
-impl_op!(Sub, sub, VV, #u32, VV);
-impl_op!(Sub, sub, VV, #u64, VV);
-impl_op!(Sub, sub, VV, #u128, VV);
-
-impl_op!(Mul, mul, Wire, #bool, V);
-impl_op!(Mul, mul, Wire, #u8, V);
-impl_op!(Mul, mul, Wire, #u16, V);
-impl_op!(Mul, mul, Wire, #u32, V);
-impl_op!(Mul, mul, Wire, #u64, V);
-impl_op!(Mul, mul, Wire, #u128, V);
-
-impl_op!(Mul, mul, V, #bool, V);
-impl_op!(Mul, mul, V, #u8, V);
-impl_op!(Mul, mul, V, #u16, V);
-impl_op!(Mul, mul, V, #u32, V);
-impl_op!(Mul, mul, V, #u64, V);
-impl_op!(Mul, mul, V, #u128, V);
-
-impl_op!(Mul, mul, VV, #bool, VV);
-impl_op!(Mul, mul, VV, #u8, VV);
-impl_op!(Mul, mul, VV, #u16, VV);
-impl_op!(Mul, mul, VV, #u32, VV);
-impl_op!(Mul, mul, VV, #u64, VV);
-impl_op!(Mul, mul, VV, #u128, VV);
+macro_rules! impl_neg {
+    ($ty:ident, $output:ident) => {
+        impl<F: Field> Neg for $ty<F> {
+            type Output = $output<F>;
+            fn neg(self) -> Self::Output {
+                let products = Operand::<F>::products(&self);
+                let division_by_zero = Operand::<F>::division_by_zero(&self);
+                let (val, exp) = Operand::<F>::into_parts(self);
+                $output::from_parts(-val, exp.map(Neg::neg), products, division_by_zero)
+            }
+        }
+        impl<F: Field> Neg for &$ty<F> {
+            type Output = $output<F>;
+            fn neg(self) -> Self::Output {
+                -self.clone()
+            }
+        }
+    };
+}
+
+impl_neg!(Wire, V);
+impl_neg!(V, V);
+impl_neg!(VV, VV);
+impl_neg!(Const, Const);
+
+// boolと整数を両辺に置けるようにする。整数リテラルの既定はi32なので符号付きも必要。
+macro_rules! impl_op_primitives {
+    ($trait:ident, $method:ident, $ty:ident, $output:ident) => {
+        impl_op_primitives!(@each $trait, $method, $ty, $output,
+            bool, u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+    };
+    (@each $trait:ident, $method:ident, $ty:ident, $output:ident, $($p:ident),*) => {
+        $(
+            impl_op!($trait, $method, $ty, #$p, $output);
+            impl_op!($trait, $method, #$p, $ty, $output);
+        )*
+    };
+}
+
+impl_op_primitives!(Add, add, Wire, V);
+impl_op_primitives!(Add, add, V, V);
+impl_op_primitives!(Add, add, VV, VV);
+
+impl_op_primitives!(Sub, sub, Wire, V);
+impl_op_primitives!(Sub, sub, V, V);
+impl_op_primitives!(Sub, sub, VV, VV);
+
+impl_op_primitives!(Mul, mul, Wire, V);
+impl_op_primitives!(Mul, mul, V, V);
+impl_op_primitives!(Mul, mul, VV, VV);
 
 impl_op!(Add, add, Wire, Wire, V);
 impl_op!(Add, add, Wire, V, V);
@@ -453,6 +1258,81 @@ impl_op!(Mul, mul, Wire, V, VV);
 impl_op!(Mul, mul, V, Wire, VV);
 impl_op!(Mul, mul, V, V, VV);
 
+impl_op!(Add, add, Wire, Const, V);
+impl_op!(Add, add, V, Const, V);
+impl_op!(Add, add, VV, Const, VV);
+impl_op!(Add, add, Const, Wire, V);
+impl_op!(Add, add, Const, V, V);
+impl_op!(Add, add, Const, VV, VV);
+impl_op!(Add, add, Const, Const, Const);
+
+impl_op!(Sub, sub, Wire, Const, V);
+impl_op!(Sub, sub, V, Const, V);
+impl_op!(Sub, sub, VV, Const, VV);
+impl_op!(Sub, sub, Const, Wire, V);
+impl_op!(Sub, sub, Const, V, V);
+impl_op!(Sub, sub, Const, VV, VV);
+impl_op!(Sub, sub, Const, Const, Const);
+
+impl_op!(Mul, mul, Wire, Const, V);
+impl_op!(Mul, mul, V, Const, V);
+impl_op!(Mul, mul, Const, Wire, V);
+impl_op!(Mul, mul, Const, V, V);
+impl_op!(Mul, mul, Const, Const, Const);
+impl_op!(Mul, mul, VV, Const, VV);
+impl_op!(Mul, mul, Const, VV, VV);
+
+// 割り算は値を計算せず、cs.wireで商を割り当てるまで遅らせる
+macro_rules! impl_div {
+    ($($lhs:ty),*; $rhs:tt) => {
+        $(impl_div!(@impl $lhs, $rhs<F>); impl_div!(@impl $lhs, &$rhs<F>);)*
+    };
+    (@impl $lhs:ty, $rhs:ty) => {
+        impl<F: Field> Div<$rhs> for $lhs {
+            type Output = Quotient<F>;
+            fn div(self, rhs: $rhs) -> Quotient<F> {
+                Quotient {
+                    num: self.into(),
+                    den: rhs.into(),
+                }
+            }
+        }
+    };
+}
+
+impl_div!(Wire<F>, &Wire<F>, V<F>, &V<F>, Const<F>; Wire);
+impl_div!(Wire<F>, &Wire<F>, V<F>, &V<F>, Const<F>; V);
+
+// 定数での割り算は逆数倍なので線形のまま。0で割った場所は結果に残し、
+// wireかanchorでcsに報告する。値は商のヒントと同じくゼロになる
+macro_rules! impl_div_const {
+    ($($lhs:ty => $output:ident),*) => {
+        $(impl_div_const!(@impl $lhs, $output);)*
+    };
+    (@impl $lhs:ty, $output:ident) => {
+        impl<F: Field> Div<Const<F>> for $lhs {
+            type Output = $output<F>;
+            #[track_caller]
+            fn div(self, Const(k): Const<F>) -> $output<F> {
+                let mut out = Mul::mul(self, Const(k.inverse().unwrap_or_default()));
+                if k.is_zero() {
+                    out.division_by_zero.get_or_insert(Location::caller());
+                }
+                out
+            }
+        }
+        impl<F: Field> Div<&Const<F>> for $lhs {
+            type Output = $output<F>;
+            #[track_caller]
+            fn div(self, rhs: &Const<F>) -> $output<F> {
+                self / *rhs
+            }
+        }
+    };
+}
+
+impl_div_const!(Wire<F> => V, &Wire<F> => V, V<F> => V, &V<F> => V, VV<F> => VV, &VV<F> => VV);
+
 impl<F: Field> Sum<Wire<F>> for V<F> {
     fn sum<I: Iterator<Item = Wire<F>>>(iter: I) -> Self {
         iter.map(|i| i.into())
@@ -491,3 +1371,8 @@ impl<F: Field> AddAssign<&V<F>> for V<F> {
         *self = &*self + rhs;
     }
 }
+impl<F: Field> AddAssign<Const<F>> for V<F> {
+    fn add_assign(&mut self, rhs: Const<F>) {
+        *self = &*self + rhs;
+    }
+}